@@ -0,0 +1,142 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{measure, Context, Results};
+use super::strategy::Selection;
+use crate::clock_delay;
+use crate::Format;
+
+use anyhow::Result;
+
+/// Stride between the clock delays probed during the coarse phase.
+const COARSE_STRIDE: usize = 3;
+
+/// Benchmarks as few clock delays as possible to find the best one.
+///
+/// The first pass probes a coarse subset of `VALID_VALUES` with short
+/// transfers, then refines around the passing ones with short transfers
+/// too.  The second pass runs full-length measurements only around the
+/// candidate selected from the first pass.
+pub(super) fn perform (context: &mut Context, results: &mut Results) -> Result<()> {
+	let settings = context.settings;
+	let limit    = Some(settings.probe_bytes.get_bytes() as u64);
+	let count    = clock_delay::VALID_VALUES.len();
+
+	let coarse = coarse(count);
+	print_phase(context, 1, "coarse", &coarse);
+
+	for index in coarse.iter() {
+		results.steps.extend(measure(context, 1, clock_delay::VALID_VALUES[*index], limit)?);
+	}
+
+	let rates  = results.rates();
+	let probes = coarse.iter().map(|index| (*index, ! rates[*index].is_nan())).collect::<Vec<_>>();
+	let refine = refine(&probes);
+	print_phase(context, 1, "refine", &refine);
+
+	for index in refine.iter() {
+		results.steps.extend(measure(context, 1, clock_delay::VALID_VALUES[*index], limit)?);
+	}
+
+	if let Some(selection) = settings.strategy.select(&results.rates()) {
+		let full = full(&selection);
+		print_phase(context, 2, "full", &full);
+
+		for index in full.iter() {
+			results.steps.extend(measure(context, 2, clock_delay::VALID_VALUES[*index], None)?);
+		}
+	}
+
+	Ok(())
+}
+
+/// Prints the start of the phase `name` of the pass number `pass` over the clock delays at `indexes` in `VALID_VALUES`.
+fn print_phase (context: &Context, pass: usize, name: &str, indexes: &[usize]) {
+	let delays = indexes.iter().map(|index| clock_delay::VALID_VALUES[*index]).collect::<Vec<_>>();
+
+	match context.settings.format {
+		Format::Text => println!("Pass {pass}/2, {name} phase over {} clock delay(s)", delays.len()),
+		Format::Json => println!("{}", serde_json::json!({
+			"event":        "phase",
+			"pass":         pass,
+			"phase":        name,
+			"clock_delays": delays.iter().map(|clock_delay| clock_delay::convert_to_f64(*clock_delay)).collect::<Vec<_>>(),
+		})),
+	}
+}
+
+/// Plans the coarse phase: every `COARSE_STRIDE`-th index out of `count`, plus the last one.
+fn coarse (count: usize) -> Vec<usize> {
+	let mut indexes = (0 .. count).step_by(COARSE_STRIDE).collect::<Vec<_>>();
+
+	if count > 0 && indexes.last() != Some(&(count - 1)) {
+		indexes.push(count - 1);
+	}
+
+	indexes
+}
+
+/// Plans the refine phase: all indexes between two consecutive `probes` where at least one passed.
+///
+/// The indexes between two passing probes are measured too, since the
+/// strategies need the rates of whole passing windows.  The indexes
+/// between two failing probes are left unmeasured, hence considered as
+/// failing too: a passing window narrower than `COARSE_STRIDE` lying
+/// there is missed, as documented for the --adaptive option.
+fn refine (probes: &[(usize, bool)]) -> Vec<usize> {
+	probes.windows(2)
+	.filter(|pair| pair[0].1 || pair[1].1)
+	.flat_map(|pair| pair[0].0 + 1 .. pair[1].0)
+	.collect()
+}
+
+/// Plans the full phase: the candidate of `selection` and its neighbours within its passing window.
+fn full (selection: &Selection) -> Vec<usize> {
+	let start = selection.index.saturating_sub(1).max(selection.window.start);
+	let end   = (selection.index + 1).min(selection.window.end);
+
+	(start ..= end).collect()
+}
+
+#[test]
+fn test_plan () {
+	assert_eq!(coarse(10), vec![0, 3, 6, 9]);
+	assert_eq!(coarse(11), vec![0, 3, 6, 9, 10]);
+	assert_eq!(coarse(1),  vec![0]);
+
+	let probes = [(0, false), (3, false), (6, true), (9, true), (10, false)];
+	assert_eq!(refine(&probes), vec![4, 5, 7, 8]);
+	assert_eq!(refine(&[(0, false), (3, false)]), Vec::<usize>::new());
+
+	let selection = |index, window| Selection { index, window, reason: String::new() };
+	assert_eq!(full(&selection(7, 4 .. 9)),  vec![6, 7, 8]);
+	assert_eq!(full(&selection(4, 4 .. 9)),  vec![4, 5]);
+	assert_eq!(full(&selection(9, 9 .. 9)),  vec![9]);
+}
@@ -0,0 +1,242 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use super::results::Results;
+use super::strategy::Strategy;
+use super::hysteresis::{self, Policy};
+use crate::clock_delay;
+use crate::Format;
+use anyhow::Result;
+
+/// Benchmark results of boards sharing the same model, PCB revision and interface.
+struct Lot<'a> {
+	/// Model of the boards.
+	model: &'a str,
+
+	/// Revision of the PCB of the boards, if known.
+	revision: Option<&'a str>,
+
+	/// Device-tree name of the benchmarked interface.
+	interface: &'a str,
+
+	/// Benchmark results of each board.
+	boards: Vec<&'a Results>,
+}
+
+/// Aggregates the benchmark results saved into `paths`, then recommends one clock delay per lot.
+///
+/// Results are grouped by board model, PCB revision and interface.
+/// The clock delay chosen for each board is selected with `strategy`,
+/// after its clock delays with hysteresis are handled as specified by
+/// the `hysteresis` policy.  A clock delay passes on a board if its
+/// CRC error rate, after this policy, is known and not above
+/// `threshold` (in percent), if any.
+/// The recommended clock delay of a lot is the one that passes on all
+/// its boards with the largest worst-case margin, ties are broken by
+/// the lowest mean CRC error rate.
+pub(crate) fn perform (paths: &[PathBuf], strategy: Strategy, hysteresis: Policy, threshold: Option<f32>, format: Format) -> Result<()> {
+	let all_results = paths.iter().map(|path| Results::load(path)).collect::<Result<Vec<_>>>()?;
+
+	let mut lots = BTreeMap::<_, Lot>::new();
+
+	for results in &all_results {
+		let metadata = &results.metadata;
+		let key      = (metadata.model.as_str(), metadata.revision.as_deref(), metadata.dt_name.as_str());
+
+		lots.entry(key).or_insert_with(|| Lot {
+			model:     key.0,
+			revision:  key.1,
+			interface: key.2,
+			boards:    Vec::new(),
+		}).boards.push(results);
+	}
+
+	let reports = lots.values().map(|lot| report(lot, strategy, hysteresis, threshold)).collect::<Vec<_>>();
+
+	match format {
+		Format::Json => println!("{}", serde_json::json!({ "lots": reports })),
+		Format::Text => for (lot, report) in std::iter::zip(lots.values(), &reports) {
+			print(lot, report);
+		},
+	}
+
+	Ok(())
+}
+
+/// Computes the distributions and the recommendation of a `lot`, as JSON.
+///
+/// The selection, the pass count, the CRC error rates and the margins
+/// all rely on the same rates: those of `hysteresis::apply`, where rates
+/// above `threshold` are considered as failing.
+fn report (lot: &Lot, strategy: Strategy, hysteresis: Policy, threshold: Option<f32>) -> serde_json::Value {
+	let values = &*clock_delay::VALID_VALUES;
+	let rates  = lot.boards.iter().map(|board| {
+		hysteresis::apply(hysteresis, board, &hysteresis::detect(board)).into_iter()
+		.map(|rate| if threshold.is_some_and(|threshold| rate > threshold) { f32::NAN } else { rate })
+		.collect::<Vec<_>>()
+	}).collect::<Vec<_>>();
+
+	let mut chosen = BTreeMap::<String, usize>::new();
+
+	for board_rates in &rates {
+		let key = match strategy.select(board_rates) {
+			None            => String::from("none"),
+			Some(selection) => format!("{:.2}", values[selection.index]),
+		};
+
+		*chosen.entry(key).or_default() += 1;
+	}
+
+	let mut per_delay  = Vec::new();
+	let mut candidates = Vec::new();
+
+	for (index, clock_delay) in values.iter().enumerate() {
+		let board_rates = rates.iter().map(|rates| rates[index]).filter(|rate| ! rate.is_nan()).collect::<Vec<_>>();
+		let margins     = rates.iter().map(|rates| super::get_margins(rates, index)).collect::<Option<Vec<_>>>();
+
+		let mean = board_rates.iter().sum::<f32>() / board_rates.len() as f32;
+		let max  = board_rates.iter().cloned().fold(f32::NAN, f32::max);
+
+		// Only clock delays passing on all boards can be recommended.
+		if let Some(margins) = margins {
+			let margin = margins.iter().map(|(lower, upper)| lower.min(*upper)).fold(f32::INFINITY, f32::min);
+			candidates.push((index, margin, mean));
+		}
+
+		per_delay.push(serde_json::json!({
+			"clock_delay": clock_delay::convert_to_f64(*clock_delay),
+			"passed":      board_rates.len(),
+			"mean_rate":   clock_delay::convert_to_f64(mean),
+			"max_rate":    clock_delay::convert_to_f64(max),
+		}));
+	}
+
+	let recommended = candidates.iter().max_by(|a, b| {
+		a.1.total_cmp(&b.1).then(b.2.total_cmp(&a.2))
+	});
+
+	serde_json::json!({
+		"model":       lot.model,
+		"revision":    lot.revision,
+		"interface":   lot.interface,
+		"boards":      lot.boards.len(),
+		"chosen":      chosen,
+		"per_delay":   per_delay,
+		"recommended": recommended.map(|(index, margin, _)| serde_json::json!({
+			"clock_delay": clock_delay::convert_to_f64(values[*index]),
+			"value":       clock_delay::convert_to_bits(values[*index]).ok(),
+			"margin":      clock_delay::convert_to_f64(*margin),
+		})),
+	})
+}
+
+/// Prints the `report` of a `lot` as text.
+fn print (lot: &Lot, report: &serde_json::Value) {
+	println!("Board \"{}\", PCB revision \"{}\", interface \"{}\": {} board(s)",
+	         lot.model, lot.revision.unwrap_or("unknown"), lot.interface, lot.boards.len());
+
+	println!("\tChosen RGMII GTX clock delays:");
+	if let Some(chosen) = report["chosen"].as_object() {
+		for (clock_delay, count) in chosen {
+			println!("\t\t{clock_delay:>4} ns: {count} board(s)");
+		}
+	}
+
+	println!("\tCRC error rates per RGMII GTX clock delay:");
+	for delay in report["per_delay"].as_array().into_iter().flatten() {
+		let clock_delay = delay["clock_delay"].as_f64().unwrap_or_default();
+		let passed      = delay["passed"].as_u64().unwrap_or_default();
+
+		match (delay["mean_rate"].as_f64(), delay["max_rate"].as_f64()) {
+			(Some(mean), Some(max)) => println!("\t\t{clock_delay:.2} ns: passed on {passed}/{} board(s), mean {mean:.2}%, max {max:.2}%", lot.boards.len()),
+			_                       => println!("\t\t{clock_delay:.2} ns: passed on {passed}/{} board(s)", lot.boards.len()),
+		}
+	}
+
+	let recommended = &report["recommended"];
+
+	match (recommended["clock_delay"].as_f64(), recommended["margin"].as_f64()) {
+		(Some(clock_delay), Some(margin)) => println!("\tRecommended RGMII GTX clock delay for this lot is {clock_delay:.2} ns (worst-case margin {margin:.2} ns)"),
+		_                                 => println!("\tNo RGMII GTX clock delay passes on all boards of this lot"),
+	}
+}
+
+#[test]
+fn test_report () {
+	use super::results::Step;
+
+	// Only clock delays whose rate is specified pass.
+	let board = |rates: &[(f32, u64)]| Results {
+		steps: clock_delay::VALID_VALUES.iter().map(|clock_delay| {
+			let rate = rates.iter().find(|(value, _)| value == clock_delay);
+
+			Step {
+				pass:        1,
+				repetition:  1,
+				clock_delay: *clock_delay,
+				crc_errors:  rate.map(|(_, crc_errors)| *crc_errors).unwrap_or_default(),
+				packets:     1000,
+				duration:    1.0,
+				bytes:       1_000_000,
+				error:       rate.is_none().then(|| String::from("Operation timed out")),
+				failure:     rate.is_none().then_some(super::failure::Failure::LowSpeed),
+				..Default::default()
+			}
+		}).collect(),
+		..Default::default()
+	};
+
+	let board1 = board(&[(0.5, 9), (0.75, 0), (1.0, 0), (1.25, 0), (1.5, 1)]);
+	let board2 = board(&[(1.0, 0), (1.25, 0), (1.5, 0), (1.75, 0), (2.0, 5)]);
+
+	let lot    = Lot { model: "", revision: None, interface: "eth2", boards: vec![&board1, &board2] };
+	let json = report(&lot, Strategy::default(), Policy::default(), None);
+
+	assert_eq!(json["boards"], 2);
+	assert_eq!(json["chosen"], serde_json::json!({ "1.00": 1, "1.50": 1 }));
+	assert_eq!(json["per_delay"][2], serde_json::json!({ "clock_delay": 0.5, "passed": 1, "mean_rate": 0.9, "max_rate": 0.9 }));
+	assert_eq!(json["per_delay"][4]["passed"], 2);
+	assert_eq!(json["recommended"], serde_json::json!({ "clock_delay": 1.25, "value": 5, "margin": 0.25 }));
+
+	let board3 = board(&[(2.5, 0)]);
+	let lot    = Lot { model: "", revision: None, interface: "eth2", boards: vec![&board1, &board3] };
+
+	assert_eq!(report(&lot, Strategy::default(), Policy::default(), None)["recommended"], serde_json::Value::Null);
+
+	// Rates above the threshold fail, which narrows the window of board 1.
+	let lot  = Lot { model: "", revision: None, interface: "eth2", boards: vec![&board1, &board2] };
+	let json = report(&lot, Strategy::default(), Policy::default(), Some(0.5));
+
+	assert_eq!(json["per_delay"][2], serde_json::json!({ "clock_delay": 0.5, "passed": 0, "mean_rate": null, "max_rate": null }));
+	assert_eq!(json["recommended"], serde_json::json!({ "clock_delay": 1.25, "value": 5, "margin": 0.25 }));
+}
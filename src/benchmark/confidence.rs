@@ -0,0 +1,172 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+/// Gets the Clopper-Pearson interval of the rate of `errors` out of `trials`, at the specified `confidence` level.
+///
+/// Both bounds are exact, they are found by bisection on the binomial
+/// cumulative distribution, computed with the regularized incomplete
+/// beta function.
+pub(crate) fn interval (errors: u64, trials: u64, confidence: f64) -> (f64, f64) {
+	if trials == 0 {
+		return (0.0, 1.0);
+	}
+
+	let alpha = (1.0 - confidence) / 2.0;
+	let (x, n) = (errors as f64, trials as f64);
+
+	// P(X >= x) is increasing with the rate, P(X <= x) is decreasing.
+	let lower = match errors {
+		0 => 0.0,
+		_ => bisect(|rate| beta_inc(x, n - x + 1.0, rate) < alpha),
+	};
+
+	let upper = match errors {
+		_ if errors >= trials => 1.0,
+		_                     => bisect(|rate| beta_inc(n - x, x + 1.0, 1.0 - rate) > alpha),
+	};
+
+	(lower, upper)
+}
+
+/// Converts the frame error `rate` into a bit error rate, for frames of `bits` bits in average.
+///
+/// A frame is corrupted as soon as one of its bits is, hence the
+/// frame error rate is 1 - (1 - BER) ^ bits.
+pub(crate) fn bit_error_rate (rate: f64, bits: f64) -> f64 {
+	-((-rate).ln_1p() / bits).exp_m1()
+}
+
+/// Finds the highest rate in [0, 1] for which `below` holds, `below` being true up to some rate only.
+fn bisect<F: Fn(f64) -> bool> (below: F) -> f64 {
+	let (mut low, mut high) = (0.0, 1.0);
+
+	for _ in 0 .. 100 {
+		let middle = (low + high) / 2.0;
+
+		match below(middle) {
+			true  => low  = middle,
+			false => high = middle,
+		}
+	}
+
+	low
+}
+
+/// Gets the regularized incomplete beta function I_x(a, b), see "Numerical Recipes", section 6.4.
+fn beta_inc (a: f64, b: f64, x: f64) -> f64 {
+	if x <= 0.0 {
+		return 0.0;
+	}
+
+	if x >= 1.0 {
+		return 1.0;
+	}
+
+	let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+
+	// The continued fraction converges quickly on this side only.
+	match x < (a + 1.0) / (a + b + 2.0) {
+		true  => front * beta_cf(a, b, x) / a,
+		false => 1.0 - front * beta_cf(b, a, 1.0 - x) / b,
+	}
+}
+
+/// Evaluates the continued fraction of the incomplete beta function with the modified Lentz's method.
+fn beta_cf (a: f64, b: f64, x: f64) -> f64 {
+	const TINY: f64 = 1e-300;
+
+	let mut c = 1.0;
+	let mut d = 1.0 - (a + b) * x / (a + 1.0);
+	d = 1.0 / if d.abs() < TINY { TINY } else { d };
+
+	let mut result = d;
+
+	for m in 1 .. 10_000 {
+		let m  = m as f64;
+		let m2 = 2.0 * m;
+
+		for coefficient in [m * (b - m) * x / ((a + m2 - 1.0) * (a + m2)), -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0))] {
+			d = 1.0 + coefficient * d;
+			d = 1.0 / if d.abs() < TINY { TINY } else { d };
+			c = 1.0 + coefficient / c;
+			c = if c.abs() < TINY { TINY } else { c };
+			result *= d * c;
+		}
+
+		if (d * c - 1.0).abs() < 1e-15 {
+			break;
+		}
+	}
+
+	result
+}
+
+/// Gets the natural logarithm of the gamma function, with the Lanczos approximation (g = 7, n = 9).
+fn ln_gamma (x: f64) -> f64 {
+	const COEFFICIENTS: [f64; 9] = [
+		0.999_999_999_999_809_9,
+		676.520_368_121_885_1,
+		-1_259.139_216_722_402_8,
+		771.323_428_777_653_1,
+		-176.615_029_162_140_6,
+		12.507_343_278_686_905,
+		-0.138_571_095_265_720_12,
+		9.984_369_578_019_572e-6,
+		1.505_632_735_149_311_6e-7,
+	];
+
+	let x   = x - 1.0;
+	let sum = COEFFICIENTS[1 ..].iter().enumerate().fold(COEFFICIENTS[0], |sum, (index, coefficient)| sum + coefficient / (x + index as f64 + 1.0));
+	let t   = x + 7.5;
+
+	0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[test]
+fn test_interval () {
+	let round = |value: f64| (value * 1e5).round() / 1e5;
+
+	let (lower, upper) = interval(0, 100, 0.95);
+	assert_eq!(lower, 0.0);
+	assert_eq!(round(upper), round(1.0 - 0.025_f64.powf(1.0 / 100.0)));
+
+	let (lower, upper) = interval(5, 100, 0.95);
+	assert_eq!((round(lower), round(upper)), (0.01643, 0.11283));
+
+	let (lower, upper) = interval(1363, 95451, 0.95);
+	assert!(lower < 1363.0 / 95451.0 && 1363.0 / 95451.0 < upper);
+	assert!(upper - lower < 0.002);
+
+	assert_eq!(interval(10, 10, 0.95).1, 1.0);
+	assert_eq!(interval(0, 0, 0.95), (0.0, 1.0));
+
+	assert_eq!(round(ln_gamma(5.0).exp()), 24.0);
+	assert_eq!(round(bit_error_rate(0.5, 1.0)), 0.5);
+}
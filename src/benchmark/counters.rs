@@ -0,0 +1,126 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::ethtool;
+use anyhow::Result;
+
+/// Source of the number of CRC errors and received packets.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Counters {
+	/// NIC statistics named `errors` and `packets`, as reported by ethtool.
+	Ethtool {
+		errors:  String,
+		packets: String,
+	},
+
+	/// Generic interface statistics, as reported by rtnetlink.
+	Rtnetlink,
+}
+
+impl Counters {
+	/// Detects the most suitable counters for the specified `device`.
+	///
+	/// Driver-specific counters are preferred since they are usually
+	/// more accurate, then ethtool standard MAC statistics, and
+	/// finally generic interface statistics which are always available.
+	pub fn detect (device: &str) -> Result<Self> {
+		let nic_stats = ethtool::get_nic_stats(device).unwrap_or_else(|error| {
+			log::warn!("{error}");
+			Default::default()
+		});
+
+		for (errors, packets) in ETHTOOL_PRESETS {
+			if nic_stats.contains_key(errors) && nic_stats.contains_key(packets) {
+				return Ok(Counters::Ethtool { errors: String::from(errors), packets: String::from(packets) });
+			}
+		}
+
+		Ok(Counters::Rtnetlink)
+	}
+
+	/// Reads the current number of CRC errors and received packets of the specified `device`.
+	pub fn read (&self, device: &str) -> Result<(u64, u64)> {
+		let (stats, errors, packets) = match self {
+			Counters::Ethtool { errors, packets } => (ethtool::get_nic_stats(device)?, errors.as_str(), packets.as_str()),
+			Counters::Rtnetlink                   => (crate::rtnetlink::get_stats64(device)?, "rx_crc_errors", "rx_packets"),
+		};
+
+		let get = |key| stats.get(key).copied()
+		                .ok_or(anyhow!("can't find statistic named \"{key}\" for device {device}"));
+
+		Ok((get(errors)?, get(packets)?))
+	}
+}
+
+impl std::fmt::Display for Counters {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		match self {
+			Counters::Ethtool { errors, packets } => write!(formatter, "{errors}/{packets} from ethtool"),
+			Counters::Rtnetlink                   => write!(formatter, "rx_crc_errors/rx_packets from rtnetlink"),
+		}
+	}
+}
+
+/// Parses a `&str` into `Counters` for the `--counter` option.
+///
+/// The value is either "stmmac", "eth-mac", "rtnetlink", or the names
+/// of any two ethtool statistics separated by a comma, ex.
+/// "mmc_rx_crc_error,rx_pkt_n".
+pub(crate) fn parser (value: &str) -> Result<Counters> {
+	let ethtool = |(errors, packets): (&str, &str)| Counters::Ethtool { errors: String::from(errors), packets: String::from(packets) };
+
+	match value {
+		"stmmac"    => Ok(ethtool(ETHTOOL_PRESETS[0])),
+		"eth-mac"   => Ok(ethtool(ETHTOOL_PRESETS[1])),
+		"rtnetlink" => Ok(Counters::Rtnetlink),
+		_           => match value.split_once(',') {
+			Some((errors, packets)) if ! errors.is_empty() && ! packets.is_empty() => Ok(ethtool((errors, packets))),
+			_ => bail!("must be \"stmmac\", \"eth-mac\", \"rtnetlink\" or \"ERRORS,PACKETS\""),
+		}
+	}
+}
+
+#[test]
+fn test_parser () {
+	let ethtool = |errors: &str, packets: &str| Counters::Ethtool { errors: String::from(errors), packets: String::from(packets) };
+
+	assert_eq!(parser("stmmac").unwrap(),    ethtool("mmc_rx_crc_error", "rx_pkt_n"));
+	assert_eq!(parser("eth-mac").unwrap(),   ethtool("eth-mac.FrameCheckSequenceErrors", "eth-mac.FramesReceivedOK"));
+	assert_eq!(parser("rtnetlink").unwrap(), Counters::Rtnetlink);
+	assert_eq!(parser("a,b").unwrap(),       ethtool("a", "b"));
+	assert!(parser("a,").is_err());
+	assert!(parser("a").is_err());
+}
+
+/// Known pairs of ethtool statistics for CRC errors and received packets, by order of preference.
+const ETHTOOL_PRESETS: [(&str, &str); 2] = [
+	("mmc_rx_crc_error",                 "rx_pkt_n"),
+	("eth-mac.FrameCheckSequenceErrors", "eth-mac.FramesReceivedOK"),
+];
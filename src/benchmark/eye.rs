@@ -0,0 +1,177 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::fmt::Write as _;
+
+use super::results::Results;
+use super::strategy::Selection;
+use crate::clock_delay;
+
+/// Width of the bars of the error rate chart, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Resolution of the eye diagram, in nanoseconds per character.
+const EYE_RESOLUTION: f32 = 0.05;
+
+/// Renders the eye-window report of the `selection` made from `results`, whose CRC error `rates` were used.
+///
+/// Since clock delays are not evenly spaced (0, 0.3, 0.5, 0.75 ns, ...),
+/// the actual edges of the passing window lie somewhere between the
+/// outermost passing clock delays and their failing neighbours, which
+/// are both reported along with the resulting bounds of the width and
+/// of the margins.  The eye diagram is drawn to scale.
+pub(crate) fn render (results: &Results, rates: &[f32], selection: &Selection) -> String {
+	let values = &*clock_delay::VALID_VALUES;
+	let window = &selection.window;
+	let best   = values[selection.index];
+
+	// Failing neighbours of the window, if any.
+	let below = window.start.checked_sub(1).map(|index| values[index]);
+	let above = values.get(window.end + 1).cloned();
+
+	let (lower, upper) = selection.edges();
+
+	let mut output = String::new();
+
+	// Writing into a String can't fail.
+	let _ = writeln!(output, "Eye window:");
+
+	match below {
+		Some(below) => { let _ = writeln!(output, "\tlower edge is between {below:.2} and {lower:.2} ns"); }
+		None        => { let _ = writeln!(output, "\tlower edge is below {lower:.2} ns, the lowest clock delay"); }
+	}
+
+	match above {
+		Some(above) => { let _ = writeln!(output, "\tupper edge is between {upper:.2} and {above:.2} ns"); }
+		None        => { let _ = writeln!(output, "\tupper edge is above {upper:.2} ns, the highest clock delay"); }
+	}
+
+	let at_most = |bound: Option<f32>| bound.map(|bound| format!("{bound:.2} ns")).unwrap_or(String::from("unknown"));
+
+	let _ = writeln!(output, "\twidth is at least {:.2} ns, at most {}", upper - lower, at_most(below.zip(above).map(|(below, above)| above - below)));
+	let _ = writeln!(output, "\tmargin of {best:.2} ns is at least {:.2} ns below (at most {}) and at least {:.2} ns above (at most {})",
+	                 best - lower, at_most(below.map(|below| best - below)),
+	                 upper - best, at_most(above.map(|above| above - best)));
+
+	let _ = writeln!(output, "Eye diagram (# passing, x failing, ^ selected, one character per {EYE_RESOLUTION:.2} ns):");
+	let _ = writeln!(output, "\t{}", render_eye(rates, selection));
+
+	let passes   = results.passes();
+	let max_rate = values.iter()
+	               .flat_map(|clock_delay| (1 ..= passes).filter_map(|pass| results.summary(Some(pass), *clock_delay)))
+	               .map(|summary| summary.mean)
+	               .filter(|rate| ! rate.is_nan())
+	               .fold(0.0, f32::max);
+
+	let _ = writeln!(output, "Mean CRC error rate per clock delay and pass:");
+
+	for (index, clock_delay) in values.iter().enumerate() {
+		for pass in 1 ..= passes {
+			let label = if pass == 1 { format!("{clock_delay:.2} ns") } else { String::new() };
+			let mark  = if index == selection.index && pass == 1 { '^' } else { ' ' };
+
+			let bar = match results.summary(Some(pass), *clock_delay) {
+				None                                  => String::from("not benchmarked"),
+				Some(summary) if summary.failures > 0 => String::from("failed"),
+				Some(summary)                         => {
+					let rate   = summary.mean;
+					let length = if max_rate > 0.0 { (rate / max_rate * BAR_WIDTH as f32).round() as usize } else { 0 };
+					format!("{:<BAR_WIDTH$} {rate:.2}%", "#".repeat(length))
+				}
+			};
+
+			let _ = writeln!(output, "\t{label:>7} {mark} pass {pass} |{bar}");
+		}
+	}
+
+	output
+}
+
+/// Renders the eye diagram of the CRC error `rates`, where each clock delay is placed to scale.
+fn render_eye (rates: &[f32], selection: &Selection) -> String {
+	let values = &*clock_delay::VALID_VALUES;
+
+	let column = |value: f32| (value / EYE_RESOLUTION).round() as usize;
+	let last   = values.last().cloned().unwrap_or_default();
+
+	let mut eye = vec![' '; column(last) + 1];
+
+	// The passing window is drawn between its outermost clock delays.
+	let (lower, upper) = selection.edges();
+	for cell in &mut eye[column(lower) ..= column(upper)] {
+		*cell = '=';
+	}
+
+	for (index, value) in values.iter().enumerate() {
+		eye[column(*value)] = match () {
+			_ if index == selection.index => '^',
+			_ if rates[index].is_nan()    => 'x',
+			_                             => '#',
+		};
+	}
+
+	format!("0 ns [{}] {last:.2} ns", eye.into_iter().collect::<String>())
+}
+
+#[test]
+fn test_render_eye () {
+	use super::results::Step;
+
+	let passing = [0.3, 0.5, 0.75, 1.0, 1.25];
+
+	let results = Results {
+		steps: clock_delay::VALID_VALUES.iter().map(|clock_delay| Step {
+			pass:        1,
+			repetition:  1,
+			clock_delay: *clock_delay,
+			crc_errors:  0,
+			packets:     1000,
+			duration:    1.0,
+			bytes:       1_000_000,
+			error:       (! passing.contains(clock_delay)).then(|| String::from("Operation timed out")),
+			failure:     (! passing.contains(clock_delay)).then_some(super::failure::Failure::LowSpeed),
+			..Default::default()
+		}).collect(),
+		..Default::default()
+	};
+
+	let selection = Selection { index: 3, window: 1 .. 5, reason: String::new() };
+
+	assert_eq!(render_eye(&results.rates(), &selection),
+	           "0 ns [x     #===#====^====#====#    x    x    x    x    x    x    x    x] 3.25 ns");
+
+	let report = render(&results, &results.rates(), &selection);
+	assert!(report.contains("\tlower edge is between 0.00 and 0.30 ns\n"));
+	assert!(report.contains("\tupper edge is between 1.25 and 1.50 ns\n"));
+	assert!(report.contains("\twidth is at least 0.95 ns, at most 1.50 ns\n"));
+	assert!(report.contains("\tmargin of 0.75 ns is at least 0.45 ns below (at most 0.75 ns) and at least 0.50 ns above (at most 0.75 ns)\n"));
+	assert!(report.contains("\t0.75 ns ^ pass 1 |                                         0.00%\n"));
+	assert!(report.contains("\t0.00 ns   pass 1 |failed\n"));
+}
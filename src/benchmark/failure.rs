@@ -0,0 +1,145 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+/// Categories of failed downloads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Failure {
+	/// Host name couldn't be resolved
+	Dns,
+
+	/// Connection to the server couldn't be established
+	Connect,
+
+	/// TLS handshake or certificate verification failed
+	Tls,
+
+	/// Server replied with an HTTP error status
+	HttpStatus,
+
+	/// Transfer was slower than the specified limit
+	LowSpeed,
+
+	/// Link went down during the transfer
+	LinkDown,
+}
+
+impl Failure {
+	/// All categories, in the order they are documented.
+	const ALL: [Failure; 6] = [Failure::Dns, Failure::Connect, Failure::Tls, Failure::HttpStatus, Failure::LowSpeed, Failure::LinkDown];
+
+	/// Gets the name of this failure, as saved into results, ex. "http-status".
+	pub fn name (self) -> &'static str {
+		match self {
+			Failure::Dns        => "dns",
+			Failure::Connect    => "connect",
+			Failure::Tls        => "tls",
+			Failure::HttpStatus => "http-status",
+			Failure::LowSpeed   => "low-speed",
+			Failure::LinkDown   => "link-down",
+		}
+	}
+
+	/// Gets the failure named `name`, see `Failure::name`.
+	pub fn from_name (name: &str) -> Option<Self> {
+		Failure::ALL.into_iter().find(|failure| failure.name() == name)
+	}
+
+	/// Classifies the curl `error`, `link_down` telling whether the link was found down right after it.
+	///
+	/// Errors that fit none of the categories are not classified.
+	pub fn classify (error: &curl::Error, link_down: bool) -> Option<Self> {
+		let detail   = error.extra_description().unwrap_or_default();
+		let timedout = error.is_operation_timedout();
+
+		match () {
+			_ if link_down => Some(Failure::LinkDown),
+
+			_ if error.is_couldnt_resolve_host()
+			  || error.is_couldnt_resolve_proxy()
+			  || (timedout && detail.starts_with("Resolving")) => Some(Failure::Dns),
+
+			_ if error.is_couldnt_connect()
+			  || (timedout && (detail.starts_with("Connection") || detail.starts_with("Failed to connect"))) => Some(Failure::Connect),
+
+			_ if error.is_ssl_connect_error()
+			  || error.is_peer_failed_verification()
+			  || error.is_ssl_certproblem()
+			  || error.is_ssl_cacert()
+			  || (timedout && detail.starts_with("SSL")) => Some(Failure::Tls),
+
+			_ if error.is_http_returned_error() => Some(Failure::HttpStatus),
+			_ if timedout                       => Some(Failure::LowSpeed),
+			_                                   => None,
+		}
+	}
+
+	/// Whether this failure happened while setting up the transfer, before any payload was received.
+	///
+	/// Such failures might be caused by the network rather than by the
+	/// clock delay, hence they are retried then double-checked.
+	pub fn is_setup (self) -> bool {
+		matches!(self, Failure::Dns | Failure::Connect | Failure::Tls)
+	}
+}
+
+impl std::fmt::Display for Failure {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		formatter.write_str(self.name())
+	}
+}
+
+#[test]
+fn test_classify () {
+	let error = |code, detail: &str| {
+		let mut error = curl::Error::new(code);
+		error.set_extra(String::from(detail));
+		error
+	};
+
+	let resolving = error(28, "Resolving timed out after 5000 milliseconds");
+	let too_slow  = error(28, "Operation too slow. Less than 102400 bytes/sec transferred the last 5 seconds");
+	let connect   = error(28, "Connection timed out after 5001 milliseconds");
+
+	assert_eq!(Failure::classify(&resolving, false),                            Some(Failure::Dns));
+	assert_eq!(Failure::classify(&error(6, ""), false),                         Some(Failure::Dns));
+	assert_eq!(Failure::classify(&connect, false),                              Some(Failure::Connect));
+	assert_eq!(Failure::classify(&error(7, ""), false),                         Some(Failure::Connect));
+	assert_eq!(Failure::classify(&error(35, ""), false),                        Some(Failure::Tls));
+	assert_eq!(Failure::classify(&error(22, ""), false),                        Some(Failure::HttpStatus));
+	assert_eq!(Failure::classify(&too_slow, false),                             Some(Failure::LowSpeed));
+	assert_eq!(Failure::classify(&too_slow, true),                              Some(Failure::LinkDown));
+	assert_eq!(Failure::classify(&error(37, ""), false),                        None);
+
+	assert!(Failure::Dns.is_setup());
+	assert!(! Failure::LowSpeed.is_setup());
+	assert_eq!(Failure::HttpStatus.to_string(), "http-status");
+	assert_eq!(Failure::from_name("link-down"), Some(Failure::LinkDown));
+	assert_eq!(Failure::from_name("unknown"),   None);
+}
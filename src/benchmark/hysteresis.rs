@@ -0,0 +1,225 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::results::Results;
+use crate::clock_delay;
+
+/// Score of the two-proportion z-test above which CRC error rates of two passes are inconsistent, ie. p < 0.003.
+const Z_THRESHOLD: f64 = 3.0;
+
+/// How clock delays with inconsistent outcomes between passes are handled when selecting the best one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub(crate) enum Policy {
+	/// Consider them as failing
+	#[default]
+	Exclude,
+
+	/// Consider them with the CRC error rate of their worst pass, unless they failed in any pass
+	DownWeight,
+
+	/// Only report them
+	Ignore,
+}
+
+/// Inconsistency of a clock delay between passes, which indicates the link state depends on history.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Finding {
+	/// Index of the clock delay in `VALID_VALUES`.
+	pub index: usize,
+
+	/// Whether this clock delay failed in some passes only, rather than having significantly different CRC error rates.
+	pub failed: bool,
+
+	/// Description of the inconsistency.
+	pub description: String,
+}
+
+/// Detects the clock delays whose outcome differs between the passes of `results`.
+///
+/// A clock delay is inconsistent if it failed in some passes but not in
+/// others, or if the CRC error rates of two passes are significantly
+/// different according to a two-proportion z-test.  Adaptive results
+/// are never inconsistent: their passes measure with different lengths
+/// and over different clock delays.
+pub(crate) fn detect (results: &Results) -> Vec<Finding> {
+	let mut findings = Vec::new();
+
+	if results.is_adaptive() {
+		return findings;
+	}
+
+	for (index, clock_delay) in clock_delay::VALID_VALUES.iter().enumerate() {
+		// Total number of CRC errors and packets of each pass, None if it failed.
+		let passes = (1 ..= results.passes()).filter_map(|pass| {
+			let steps = results.steps.iter().filter(|step| step.pass == pass && step.clock_delay == *clock_delay).collect::<Vec<_>>();
+
+			match steps.iter().any(|step| step.error.is_some()) {
+				_ if steps.is_empty() => None,
+				true                  => Some((pass, None)),
+				false                 => Some((pass, Some((steps.iter().map(|step| step.crc_errors).sum::<u64>(),
+				                                           steps.iter().map(|step| step.packets).sum::<u64>())))),
+			}
+		}).collect::<Vec<_>>();
+
+		let describe = |pass: usize| format!("pass {pass} ({})", results.direction(pass));
+
+		let failed = passes.iter().filter(|(_, totals)| totals.is_none()).map(|(pass, _)| describe(*pass)).collect::<Vec<_>>();
+		let passed = passes.iter().filter(|(_, totals)| totals.is_some()).map(|(pass, _)| describe(*pass)).collect::<Vec<_>>();
+
+		if ! failed.is_empty() && ! passed.is_empty() {
+			findings.push(Finding {
+				index,
+				failed:      true,
+				description: format!("passed in {} but failed in {}", passed.join(", "), failed.join(", ")),
+			});
+			continue;
+		}
+
+		let totals = passes.iter().filter_map(|(pass, totals)| totals.map(|totals| (*pass, totals))).collect::<Vec<_>>();
+
+		let inconsistent = totals.iter().enumerate().flat_map(|(position, a)| totals[position + 1 ..].iter().map(move |b| (a, b)))
+		                   .find(|((_, a), (_, b))| z_score(*a, *b).abs() > Z_THRESHOLD);
+
+		if let Some(((pass1, (errors1, packets1)), (pass2, (errors2, packets2)))) = inconsistent {
+			findings.push(Finding {
+				index,
+				failed:      false,
+				description: format!("CRC error rate was {:.2}% in {} but {:.2}% in {}",
+				                     (100 * errors1) as f64 / *packets1 as f64, describe(*pass1),
+				                     (100 * errors2) as f64 / *packets2 as f64, describe(*pass2)),
+			});
+		}
+	}
+
+	findings
+}
+
+/// Gets the CRC error rate of each clock delay in `VALID_VALUES` after applying `policy` to the inconsistent ones.
+pub(crate) fn apply (policy: Policy, results: &Results, findings: &[Finding]) -> Vec<f32> {
+	let mut rates = results.rates();
+
+	for finding in findings {
+		rates[finding.index] = match (policy, finding.failed) {
+			(Policy::Ignore, _) | (_, true) => rates[finding.index],
+			(Policy::Exclude, false)        => f32::NAN,
+			(Policy::DownWeight, false)     => {
+				(1 ..= results.passes())
+				.filter_map(|pass| results.summary(Some(pass), clock_delay::VALID_VALUES[finding.index]))
+				.map(|summary| summary.mean)
+				.fold(rates[finding.index], f32::max)
+			}
+		};
+	}
+
+	rates
+}
+
+/// Gets the z-score of the difference between two proportions, each given as `(errors, packets)`.
+fn z_score ((errors1, packets1): (u64, u64), (errors2, packets2): (u64, u64)) -> f64 {
+	if packets1 == 0 || packets2 == 0 {
+		return 0.0;
+	}
+
+	let (errors1, packets1, errors2, packets2) = (errors1 as f64, packets1 as f64, errors2 as f64, packets2 as f64);
+
+	let pooled   = (errors1 + errors2) / (packets1 + packets2);
+	let variance = pooled * (1.0 - pooled) * (1.0 / packets1 + 1.0 / packets2);
+
+	if variance == 0.0 {
+		return 0.0;
+	}
+
+	(errors1 / packets1 - errors2 / packets2) / variance.sqrt()
+}
+
+impl std::fmt::Display for Policy {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		super::format_value_enum(self, formatter)
+	}
+}
+
+#[test]
+fn test_detect () {
+	use super::results::Step;
+
+	let step = |pass, clock_delay, crc_errors, error: bool| Step {
+		pass,
+		repetition: 1,
+		clock_delay,
+		crc_errors,
+		packets:    100_000,
+		duration:   1.0,
+		bytes:      1_000_000,
+		error:      error.then(|| String::from("Operation timed out")),
+		failure:    error.then_some(super::failure::Failure::LowSpeed),
+		..Default::default()
+	};
+
+	let results = Results {
+		steps: vec![
+			step(1, 0.0, 0, true),
+			step(1, 0.3, 0, true),
+			step(1, 0.5, 1430, false),
+			step(1, 0.75, 0, false),
+			step(2, 0.75, 1500, false),
+			step(2, 0.5, 1520, false),
+			step(2, 0.3, 10, false),
+			step(2, 0.0, 0, true),
+		],
+		..Default::default()
+	};
+
+	let findings = detect(&results);
+
+	assert_eq!(findings, vec![
+		Finding { index: 1, failed: true,  description: String::from("passed in pass 2 (descending) but failed in pass 1 (ascending)") },
+		Finding { index: 3, failed: false, description: String::from("CRC error rate was 0.00% in pass 1 (ascending) but 1.50% in pass 2 (descending)") },
+	]);
+
+	let rates = apply(Policy::Exclude, &results, &findings);
+	assert!(rates[1].is_nan() && rates[3].is_nan());
+	assert!(! rates[2].is_nan());
+
+	let rates = apply(Policy::DownWeight, &results, &findings);
+	assert!(rates[1].is_nan());
+	assert_eq!(rates[3], 1.5);
+
+	let rates = apply(Policy::Ignore, &results, &findings);
+	assert_eq!(rates[3], 0.75);
+
+	assert!(z_score((1430, 100_000), (1520, 100_000)).abs() < Z_THRESHOLD);
+	assert_eq!(z_score((0, 100_000), (0, 100_000)), 0.0);
+
+	let mut adaptive = results.clone();
+	adaptive.metadata.order = Some(String::from("adaptive"));
+	assert_eq!(detect(&adaptive), vec![]);
+
+	assert_eq!(Policy::DownWeight.to_string(), "down-weight");
+}
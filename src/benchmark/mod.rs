@@ -28,62 +28,399 @@
 // (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
 // OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-mod ethtool;
+pub(crate) mod counters;
+pub(crate) mod results;
+pub(crate) mod aggregate;
+pub(crate) mod strategy;
+pub(crate) mod eye;
+pub(crate) mod sweep;
+pub(crate) mod hysteresis;
+mod adaptive;
+pub(crate) mod confidence;
+pub(crate) mod failure;
+pub(crate) mod resolve;
 
 use crate::clock_delay;
 use crate::device_tree;
+use crate::ethtool;
+use crate::rtnetlink;
+use crate::Format;
+use counters::Counters;
+use results::{Metadata, Results, Step};
+use strategy::Strategy;
+use sweep::{Order, Sweep};
+use hysteresis::Policy;
+use failure::Failure;
 
 use byte_unit::Byte;
-use std::time::{Instant, Duration};
+use std::time::{Instant, Duration, SystemTime};
 use std::ops::Range;
-use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use anyhow::{Context as _, Result};
 
-/// Benchmarks the `device` using the specified `url`.
+/// Interval between two checks of the link state while waiting for it to run again.
+const LINK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Settings of a benchmark.
+pub(crate) struct Settings {
+	/// URL of the payload downloaded at each step.
+	pub url: String,
+
+	/// Skip if transfer rate is below `speed_low_limit`/second during more than `timeout` seconds.
+	pub speed_low_limit: Byte,
+
+	/// Timeout for `speed_low_limit` and for the connection phase, in seconds.
+	pub timeout: u64,
+
+	/// Counters of CRC errors and received packets, auto-detected if not specified.
+	pub counters: Option<Counters>,
+
+	/// Print the non-zero deltas of all NIC statistics at each step.
+	pub stats_diff: bool,
+
+	/// File where the non-zero deltas of all NIC statistics are saved at each step, as JSON lines.
+	pub stats_report: Option<PathBuf>,
+
+	/// File where all raw measurements are saved, as JSON.
+	pub save: Option<PathBuf>,
+
+	/// Revision of the PCB, saved along with the raw measurements.
+	pub revision: Option<String>,
+
+	/// Number of passes over all clock delays.
+	pub passes: usize,
+
+	/// Number of measurements of each clock delay within a pass.
+	pub repeat: usize,
+
+	/// Order of the clock delays within each pass.
+	pub order: Order,
+
+	/// Seed of the random order, generated if not specified.
+	pub seed: Option<u64>,
+
+	/// Strategy to select the best clock delay.
+	pub strategy: Strategy,
+
+	/// How clock delays with inconsistent outcomes between passes are handled.
+	pub hysteresis: Policy,
+
+	/// Print the eye-window report along with the best clock delay.
+	pub eye: bool,
+
+	/// Probe a coarse subset of clock delays, refine around the passing ones, then measure fully around the candidate only.
+	pub adaptive: bool,
+
+	/// Number of bytes downloaded by each short transfer of the adaptive sweep.
+	pub probe_bytes: Byte,
+
+	/// Maximum number of bytes downloaded at each step, the whole payload if not specified.
+	pub bytes_per_step: Option<Byte>,
+
+	/// Maximum duration of the download at each step, unlimited if not specified.
+	pub seconds_per_step: Option<Duration>,
+
+	/// Confidence level of the CRC error rate intervals and of the bit error rate upper bounds.
+	pub confidence: f64,
+
+	/// Keep downloading at each step until the CRC error rate is known to be either below or above this target, in percent.
+	pub target_rate: Option<f64>,
+
+	/// Maximum number of bytes downloaded at each step when a target CRC error rate is specified.
+	pub max_bytes_per_step: Byte,
+
+	/// Number of retries of the steps failing while setting up the transfer.
+	pub retries: usize,
+
+	/// Reuse the same connection from one download to another.
+	pub keep_alive: bool,
+
+	/// Time waited after each change of clock delay, once the link runs again.
+	pub settle: Duration,
+
+	/// Output format, JSON progress events are printed one per line.
+	pub format: Format,
+}
+
+/// State shared by all steps of a benchmark.
+struct Context<'a> {
+	/// Clock delay of the benchmarked device.
+	handle: clock_delay::Handle,
+
+	/// Counters of CRC errors and received packets.
+	counters: Counters,
+
+	/// Settings of this benchmark.
+	settings: &'a Settings,
+
+	/// Where the NIC statistics deltas are saved, if requested.
+	stats_report: Option<std::fs::File>,
+
+	/// Clock delay when the benchmark started, assumed to be working.
+	reference: f32,
+
+	/// Address of the server resolved when the benchmark started, as a curl "HOST:PORT:ADDRESS" entry.
+	pinned: Option<String>,
+
+	/// Handle shared by all downloads, so that connections can be reused.
+	curl: curl::easy::Easy,
+}
+
+/// Formats `value` as named on the command line, ex. "widest-window" for `Strategy::WidestWindow`.
+fn format_value_enum<T: clap::ValueEnum + std::fmt::Debug> (value: &T, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+	match value.to_possible_value() {
+		Some(possible) => write!(formatter, "{}", possible.get_name()),
+		None           => write!(formatter, "{value:?}"),
+	}
+}
+
+/// Benchmarks the `device` using the specified `settings`.
 ///
 /// This function returns an error if either the specified
 /// `speed_low_limit` or `timeout` is reached.
-pub(crate) fn perform(device: &str, url: &str, speed_low_limit: Byte, timeout: u64) -> Result<()> {
-	let reversed_valid_values = clock_delay::VALID_VALUES.iter().cloned().rev().collect::<Vec<_>>();
+pub(crate) fn perform(device: &str, settings: &Settings) -> Result<()> {
+	let handle = clock_delay::Handle::resolve(device)?;
+	handle.ensure_writable()?;
+
+	let counters = match &settings.counters {
+		Some(counters) => counters.clone(),
+		None           => Counters::detect(&handle.device)?,
+	};
+
+	let stats_report = match &settings.stats_report {
+		None       => None,
+		Some(path) => Some(std::fs::File::create(path).map_err(|error| anyhow!("can't create {}: {error}", path.display()))?),
+	};
+
+	let reference = handle.value.get_as_ns()?;
+
+	// The host is resolved only once, while the clock delay is still
+	// working, so that the steps don't depend on DNS requests.
+	let pinned = resolve::pin(&settings.url)?;
+
+	let mut context = Context { handle, counters, settings, stats_report, reference, pinned, curl: curl::easy::Easy::new() };
+
+	let seed = settings.seed.unwrap_or_else(|| {
+		SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+	});
+
+	let seed = (settings.order == Order::Random && ! settings.adaptive).then_some(seed);
+	let order = match settings.adaptive {
+		true  => String::from("adaptive"),
+		false => settings.order.to_string(),
+	};
+
+	match settings.format {
+		Format::Text => {
+			println!("Using URL {}", settings.url);
+			println!("Using counters {}", context.counters);
+
+			if let Some(pinned) = &context.pinned {
+				println!("Using pinned address {pinned}");
+			}
+
+			if let Some(seed) = seed {
+				println!("Using random order with seed {seed}");
+			}
+		}
+		Format::Json => println!("{}", serde_json::json!({
+			"event":    "start",
+			"url":      settings.url,
+			"counters": context.counters.to_string(),
+			"passes":   settings.passes,
+			"repeat":   settings.repeat,
+			"order":    order,
+			"seed":     seed,
+			"pinned":   context.pinned,
+			"handle":   context.handle.to_json()?,
+		})),
+	}
+
+	let handle = &context.handle;
+
+	let model = device_tree::get_model().unwrap_or_else(|error| {
+		log::warn!("{error}");
+		String::from("unknown")
+	});
+
+	let metadata = Metadata {
+		device:    handle.device.clone(),
+		dt_name:   handle.dt_name.clone(),
+		gpio:      handle.gpio.to_string(),
+		nodes:     device_tree::find_nodes(&handle.gpio),
+		model,
+		revision:  settings.revision.clone(),
+		url:       settings.url.clone(),
+		counters:  context.counters.to_string(),
+		order:     Some(order),
+		seed,
+		timestamp: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+	};
+
+	let mut results = Results { metadata, steps: Vec::new() };
+
+	let mut sweep = Sweep::new(settings.order, seed.unwrap_or_default());
+
+	if settings.adaptive {
+		adaptive::perform(&mut context, &mut results)?;
+	} else {
+		for pass in 1 ..= settings.passes {
+			print_pass(settings, pass);
+			results.steps.extend(perform_single_pass(&mut context, pass, &sweep.delays(pass))?);
+		}
+	}
+
+	let bytes = results.steps.iter().map(|step| step.bytes).sum::<u64>();
+
+	match settings.format {
+		Format::Text => println!("Downloaded {} over {} step(s)", Byte::from_bytes(bytes as u128).get_appropriate_unit(true), results.steps.len()),
+		Format::Json => println!("{}", serde_json::json!({ "event": "downloaded", "bytes": bytes, "steps": results.steps.len() })),
+	}
+
+	if let Some(path) = &settings.save {
+		results.save(path)?;
+	}
+
+	recommend(&results, settings.strategy, settings.hysteresis, settings.eye, settings.confidence, settings.format)
+}
+
+/// Analyzes the benchmark results saved into `path`, then recommends the best clock delay.
+///
+/// BER upper bounds are computed at the specified `confidence` level.
+pub(crate) fn analyze(path: &Path, strategy: Strategy, hysteresis: Policy, eye: bool, confidence: f64, format: Format) -> Result<()> {
+	let results  = Results::load(path)?;
+	let metadata = &results.metadata;
 
-	println!("Using URL {url}");
+	if format == Format::Text {
+		println!("Benchmark of device \"{}\" (\"{}\" in device-tree) on board \"{}\"", metadata.device, metadata.dt_name, metadata.model);
+		println!("Using URL {}", metadata.url);
+		println!("Using counters {}", metadata.counters);
 
-	println!("Pass 1/2");
-	let results1 = perform_single_pass(device, url, speed_low_limit, timeout, &clock_delay::VALID_VALUES)?;
+		for clock_delay in clock_delay::VALID_VALUES.iter() {
+			let rates = (1 ..= results.passes()).map(|pass| {
+				let steps = results.steps.iter().filter(|step| step.pass == pass && step.clock_delay == *clock_delay).collect::<Vec<_>>();
 
-	println!("Pass 2/2");
-	let results2 = perform_single_pass(device, url, speed_low_limit, timeout, &reversed_valid_values)?;
+				match steps[..] {
+					[]     => String::from("-"),
+					[step] => match &step.error {
+						Some(error) => error.clone(),
+						None        => format!("{:.2}% ({}/{})", step.error_rate(), step.crc_errors, step.packets),
+					},
+					_ => results.summary(Some(pass), *clock_delay).map(|summary| summary.to_string()).unwrap_or_default(),
+				}
+			}).collect::<Vec<_>>();
+
+			let index = clock_delay::VALID_VALUES.iter().position(|value| value == clock_delay).unwrap();
+
+			match results.ber_upper_bound(index, confidence) {
+				Some(ber) => println!("RGMII GTX clock delay = {clock_delay:.2} nanoseconds: {}; BER < {ber:.1e} at {}% confidence",
+				                      rates.join("; "), confidence * 100.0),
+				None      => println!("RGMII GTX clock delay = {clock_delay:.2} nanoseconds: {}", rates.join("; ")),
+			}
+		}
+	}
 
-	let results = std::iter::zip(results1, results2.iter().rev()).map(|(a, b)| a + b).collect::<Vec<_>>();
+	recommend(&results, strategy, hysteresis, eye, confidence, format)
+}
 
-	let mut best_results = Vec::new();
+/// Recommends the best clock delay from the specified `results`, selected with `strategy`.
+///
+/// Clock delays with inconsistent outcomes between passes are reported,
+/// then handled as specified by the `hysteresis` policy.  The
+/// eye-window report is printed before if `eye` is set.  The BER upper
+/// bound of each clock delay is pooled over its measurements at the
+/// specified `confidence` level.
+fn recommend(results: &Results, strategy: Strategy, hysteresis: Policy, eye: bool, confidence: f64, format: Format) -> Result<()> {
+	let findings  = hysteresis::detect(results);
+	let rates     = hysteresis::apply(hysteresis, results, &findings);
+	let selection = strategy.select(&rates);
+	let nodes     = &results.metadata.nodes;
 
-	for strike in find_strikes(&results) {
-		let middle = (strike.start as f32 + strike.end as f32) / 2.0;
-		let index1 = middle.floor() as usize;
-		let index2 = middle.ceil() as usize;
-		let best   = if results[index1] < results[index2] { index1 } else { index2 };
-		best_results.push(best);
+	if format == Format::Json {
+		let best = selection.as_ref().map(|selection| clock_delay::VALID_VALUES[selection.index]);
+
+		println!("{}", serde_json::json!({
+			"event":       "result",
+			"strategy":    strategy.to_string(),
+			"hysteresis":  findings.iter().map(|finding| serde_json::json!({
+				"clock_delay": clock_delay::convert_to_f64(clock_delay::VALID_VALUES[finding.index]),
+				"failed":      finding.failed,
+				"description": finding.description,
+			})).collect::<Vec<_>>(),
+			"policy":      hysteresis.to_string(),
+			"clock_delay": best.map(clock_delay::convert_to_f64),
+			"value":       best.map(clock_delay::convert_to_bits).transpose()?,
+			"reason":      selection.as_ref().map(|selection| &selection.reason),
+			"window":      selection.as_ref().map(|selection| {
+				let (lower, upper) = selection.edges();
+				serde_json::json!({
+					"lower": clock_delay::convert_to_f64(lower),
+					"upper": clock_delay::convert_to_f64(upper),
+					"width": clock_delay::convert_to_f64(upper - lower),
+				})
+			}),
+			"margins":     selection.as_ref().map(|selection| {
+				let (lower, upper) = selection.margins();
+				serde_json::json!({ "lower": clock_delay::convert_to_f64(lower), "upper": clock_delay::convert_to_f64(upper) })
+			}),
+			"ber":         serde_json::json!({
+				"confidence":   confidence,
+				"upper_bound":  selection.as_ref().and_then(|selection| results.ber_upper_bound(selection.index, confidence)),
+				"clock_delays": clock_delay::VALID_VALUES.iter().enumerate().filter_map(|(index, value)| {
+					results.ber_upper_bound(index, confidence).map(|ber| serde_json::json!({
+						"clock_delay": clock_delay::convert_to_f64(*value),
+						"upper_bound": ber,
+					}))
+				}).collect::<Vec<_>>(),
+			}),
+			"nodes":       nodes,
+		}));
+
+		return Ok(());
 	}
 
-	best_results.sort_by(|a, b| results[*b].partial_cmp(&results[*a]).unwrap());
+	for finding in &findings {
+		println!("Hysteresis detected at {:.2} ns, {}", clock_delay::VALID_VALUES[finding.index], finding.description);
+	}
 
-	match best_results.pop() {
-		None        => println!("No reliable RGMII GTX clock delay found"),
-		Some(index) => {
-			let best_value = clock_delay::VALID_VALUES[index];
+	if ! findings.is_empty() {
+		let handling = match hysteresis {
+			Policy::Exclude    => "considered as failing",
+			Policy::DownWeight => "considered with the CRC error rate of their worst pass, unless they failed",
+			Policy::Ignore     => "not handled specifically",
+		};
+
+		println!("Clock delays with hysteresis are {handling} (policy {hysteresis})");
+	}
+
+	match selection {
+		None            => println!("No reliable RGMII GTX clock delay found"),
+		Some(selection) => {
+			let best_value                   = clock_delay::VALID_VALUES[selection.index];
+			let (lower, upper)               = selection.edges();
+			let (lower_margin, upper_margin) = selection.margins();
+
+			if eye {
+				print!("{}", eye::render(results, &rates, &selection));
+			}
 
 			println!("Best RGMII GTX clock delay is {:.2} ns", best_value);
+			println!("\tstrategy {strategy}: {}", selection.reason);
+			println!("\tpassing window is {lower:.2}-{upper:.2} ns ({:.2} ns wide)", upper - lower);
+			println!("\tmargin is {lower_margin:.2} ns below and {upper_margin:.2} ns above");
+
+			if let Some(ber) = results.ber_upper_bound(selection.index, confidence) {
+				println!("\tBER < {ber:.1e} at {}% confidence", confidence * 100.0);
+			}
 
 			let best_value = clock_delay::convert_to_bits(best_value).unwrap();
-			let dt_name    = device_tree::get_name(device)?;
-			let gpio       = clock_delay::get_gpio(&dt_name)?;
-			let nodes      = device_tree::find_nodes(&gpio);
 
 			if nodes.is_empty() {
-				log::error!("Can't find any device-tree node that uses GPIO {gpio}");
+				log::error!("Can't find any device-tree node that uses GPIO {}", results.metadata.gpio);
 			} else {
 				println!("To permanently use this RGMII GTX clock delay, add \"st,io-delay = <{best_value:#02x}>;\" into following device-tree node(s):");
-				for node in &nodes {
+				for node in nodes {
 					println!("\t{node}");
 				}
 			}
@@ -93,111 +430,576 @@ pub(crate) fn perform(device: &str, url: &str, speed_low_limit: Byte, timeout: u
 	Ok(())
 }
 
-/// Performs a single benchmark pass for the `device` using the specified `url`.
+/// Prints the start of the benchmark pass number `pass`.
+fn print_pass(settings: &Settings, pass: usize) {
+	match settings.format {
+		Format::Text => println!("Pass {pass}/{}", settings.passes),
+		Format::Json => println!("{}", serde_json::json!({ "event": "pass", "pass": pass })),
+	}
+}
+
+/// Performs the benchmark pass number `pass` over the specified `delays`.
 ///
 /// This function returns an error if either the specified
 /// `speed_low_limit` or `timeout` is reached.
-fn perform_single_pass(device: &str, url: &str, speed_low_limit: Byte, timeout: u64, delays: &[f32]) -> Result<Vec<f32>> {
-	let mut results = Vec::new();
+fn perform_single_pass(context: &mut Context, pass: usize, delays: &[f32]) -> Result<Vec<Step>> {
+	let settings = context.settings;
+
+	let mut steps = Vec::new();
 
 	for clock_delay in delays.iter() {
-		use std::io::Write;
+		steps.extend(measure(context, pass, *clock_delay, None)?);
+	}
+
+	assert_eq!(steps.len(), delays.len() * settings.repeat);
+
+	Ok(steps)
+}
 
-		let clock_delay = *clock_delay;
+/// Measures `clock_delay` as many times as requested within the pass number `pass`, transferring at most `limit` bytes each time.
+///
+/// This function returns an error if either the specified
+/// `speed_low_limit` or `timeout` is reached.
+fn measure(context: &mut Context, pass: usize, clock_delay: f32, limit: Option<u64>) -> Result<Vec<Step>> {
+	let settings = context.settings;
 
-		clock_delay::access(device, Some(clock_delay), false)?;
+	context.handle.set(clock_delay)?;
+	settle(context);
 
-		let message = format!("Benchmarking RGMII GTX clock delay = {clock_delay:.2} nanoseconds... ");
+	let steps = (1 ..= settings.repeat)
+	            .map(|repetition| perform_single_step(context, pass, repetition, clock_delay, limit))
+	            .collect::<Result<Vec<_>>>()?;
+
+	if settings.repeat > 1 {
+		let summary = results::Summary::new(&steps.iter().collect::<Vec<_>>()).unwrap();
+
+		match settings.format {
+			Format::Text => println!("\t{clock_delay:.2} ns: {summary}"),
+			Format::Json => {
+				let mut event = summary.to_json();
+				event["event"]       = "summary".into();
+				event["pass"]        = pass.into();
+				event["clock_delay"] = clock_delay::convert_to_f64(clock_delay).into();
+				println!("{event}");
+			}
+		}
+	}
+
+	Ok(steps)
+}
+
+/// Performs the measurement number `repetition` of `clock_delay` within the pass number `pass`, transferring at most `limit` bytes.
+///
+/// This function returns an error if either the specified
+/// `speed_low_limit` or `timeout` is reached.
+fn perform_single_step(context: &mut Context, pass: usize, repetition: usize, clock_delay: f32, limit: Option<u64>) -> Result<Step> {
+	use std::io::Write;
+
+	let settings = context.settings;
+
+	if settings.format == Format::Text {
+		let message = match settings.repeat {
+			1 => format!("Benchmarking RGMII GTX clock delay = {clock_delay:.2} nanoseconds... "),
+			_ => format!("Benchmarking RGMII GTX clock delay = {clock_delay:.2} nanoseconds ({repetition}/{})... ", settings.repeat),
+		};
 		let _ = std::io::stdout().write(message.as_bytes());
 		let _ = std::io::stdout().flush();
+	}
 
-		let start = get_info(device)?;
+	let before = if settings.stats_diff || context.stats_report.is_some() {
+		Some(ethtool::get_nic_stats(&context.handle.device)?)
+	} else {
+		None
+	};
+
+	// The stopping rule doesn't apply to the short transfers of the adaptive sweep.
+	let target = settings.target_rate.filter(|_| limit.is_none());
+
+	// The shortest of the requested limits applies.
+	let limit  = [limit, settings.bytes_per_step.map(|bytes| bytes.get_bytes() as u64)].into_iter().flatten().min();
+
+	// Failures while setting up the transfer are retried, since they
+	// might be caused by the network rather than by the clock delay.
+	let mut retries = 0;
 
-		let status = download(url, speed_low_limit, timeout);
-		if let Err(error) = &status {
-			if error.is_operation_timedout() {
-				println!("{error}");
-				results.push(f32::NAN);
-				continue;
+	let (mut step, status, failure) = loop {
+		let (step, status) = transfer(context, pass, repetition, clock_delay, limit, target)?;
+
+		let link_down = || step.flapped() || rtnetlink::get_link_state(&context.handle.device).is_ok_and(|link| ! link.running);
+		let failure   = status.as_ref().err().and_then(|error| Failure::classify(error, link_down()));
+
+		match failure {
+			Some(failure) if failure.is_setup() && retries < settings.retries => {
+				retries += 1;
+				log::info!("{failure} failure at {clock_delay:.2} ns, retrying ({retries}/{})", settings.retries);
 			}
+			_ => break (step, status, failure),
 		}
-		status?;
+	};
+
+	step.failure = failure;
+	step.error   = status.as_ref().err().map(ToString::to_string);
+
+	// A link flap is a failure even if the download survived it.
+	if step.error.is_none() && step.flapped() {
+		step.failure = Some(Failure::LinkDown);
+		step.error   = Some(format!("link flapped during the transfer ({} carrier changes, {} link down events)", step.carrier_changes, step.link_down_events));
+	}
+
+	// Only signal failures mark this clock delay as bad, the benchmark
+	// is aborted with a diagnosis otherwise.
+	let diagnosis = match (&status, failure) {
+		(Ok(_),      _)                         => None,
+		(Err(error), None)                      => Some(anyhow!("{error}")),
+		(Err(error), Some(Failure::HttpStatus)) => Some(anyhow!("{} replied with an HTTP error, check the URL: {error}", settings.url)),
+		(Err(_),     Some(failure)) if failure.is_setup() => check_network(context, clock_delay).err().map(|error| {
+			anyhow!("{failure} failure at both {clock_delay:.2} ns and the initial {:.2} ns clock delays, check the network setup: {error}", context.reference)
+		}),
+		(Err(_),     Some(_))                   => None,
+	};
+
+	match settings.format {
+		Format::Json => {
+			let mut event = step.to_json();
+			event["event"]           = "step".into();
+			event["ber_upper_bound"] = step.ber_upper_bound(settings.confidence).into();
+			println!("{event}");
+		}
+		Format::Text => match (&step.error, step.failure, step.ber_upper_bound(settings.confidence)) {
+			_ if diagnosis.is_some() => println!(),
+			(Some(error), Some(failure), _) if retries > 0 => println!("{failure} failure after {retries} retries: {error}"),
+			(Some(error), Some(failure), _) => println!("{failure} failure: {error}"),
+			(Some(_), None, _) => (),
+			(None, _, Some(ber)) => println!("Done in {:.2}s; CRC error rate was {:.2}% ({}/{}); BER < {ber:.1e} at {}% confidence",
+			                                 step.duration, step.error_rate(), step.crc_errors, step.packets, settings.confidence * 100.0),
+			(None, _, None) => println!("Done in {:.2}s; CRC error rate was {:.2}% ({}/{})",
+			                            step.duration, step.error_rate(), step.crc_errors, step.packets),
+		},
+	}
+
+	// Deltas are reported whatever the outcome of this step, since
+	// failures might be explained by other errors than CRC ones.
+	if let Some(before) = before {
+		let after  = ethtool::get_nic_stats(&context.handle.device)?;
+		let deltas = ethtool::diff(&before, &after);
+		report_stats(context, &step, &deltas)?;
+	}
+
+	match diagnosis {
+		Some(diagnosis) => Err(diagnosis),
+		None            => Ok(step),
+	}
+}
+
+#[test]
+fn test_measure () {
+	use clock_delay::register::{Mock, Access};
+
+	// NIC statistics are read from a fake root, the payload from a local file.
+	let root = std::env::temp_dir().join(format!("stm32mp25-rgmii-gtx-clock-delay-measure-{}", std::process::id()));
+	std::fs::create_dir_all(root.join("ethtool")).unwrap();
+	std::fs::write(root.join("ethtool/eth1"), "mmc_rx_crc_error 0\nrx_pkt_n 100\n").unwrap();
+	std::fs::write(root.join("payload"), vec![0u8; 1000]).unwrap();
+
+	let settings = Settings {
+		url:                format!("file://{}", root.join("payload").display()),
+		speed_low_limit:    Byte::from_bytes(1),
+		timeout:            5,
+		counters:           None,
+		stats_diff:         false,
+		stats_report:       None,
+		save:               None,
+		revision:           None,
+		passes:             1,
+		repeat:             2,
+		order:              Order::default(),
+		seed:               None,
+		strategy:           Strategy::default(),
+		hysteresis:         Policy::default(),
+		eye:                false,
+		adaptive:           false,
+		probe_bytes:        Byte::from_bytes(100),
+		bytes_per_step:     Some(Byte::from_bytes(100)),
+		seconds_per_step:   None,
+		confidence:         0.95,
+		target_rate:        None,
+		max_bytes_per_step: Byte::from_bytes(1000),
+		retries:            0,
+		keep_alive:         false,
+		settle:             Duration::ZERO,
+		format:             Format::Json,
+	};
+
+	let new_context = |register: Mock| Context {
+		handle: clock_delay::Handle {
+			device:  "eth1".to_string(),
+			dt_name: "ethernet@482d0000".to_string(),
+			gpio:    clock_delay::Gpio { bank: 'F', line: 7, pinctrl: "soc@0/pinctrl@44240000".to_string() },
+			address: clock_delay::Address { base: 0x4424_5040, offset: 28 },
+			value:   clock_delay::Value::new(Box::new(register), 28),
+		},
+		counters:     Counters::Ethtool { errors: "mmc_rx_crc_error".to_string(), packets: "rx_pkt_n".to_string() },
+		settings:     &settings,
+		stats_report: None,
+		reference:    0.0,
+		pinned:       None,
+		curl:         curl::easy::Easy::new(),
+	};
+
+	crate::capture::redirect(Some(root.clone()));
+
+	// The clock delay is set once, then measured as many times as requested.
+	let mock        = Mock::new(0x0000_0000);
+	let accesses    = mock.accesses();
+	let mut context = new_context(mock);
+	let steps       = measure(&mut context, 1, 0.75, None).unwrap();
+
+	assert_eq!(*accesses.borrow(), vec![Access::Read(0x0000_0000), Access::Write(0x3000_0000), Access::Read(0x3000_0000)]);
+	assert_eq!(steps.iter().map(|step| (step.repetition, step.clock_delay, step.bytes, step.failure)).collect::<Vec<_>>(),
+	           vec![(1, 0.75, 100, None), (2, 0.75, 100, None)]);
+
+	// Nothing is downloaded if the clock delay can't be set.
+	let mock        = Mock::stuck(0x0000_0000);
+	let accesses    = mock.accesses();
+	let mut context = new_context(mock);
+
+	assert!(measure(&mut context, 1, 0.75, None).is_err());
+	assert_eq!(accesses.borrow().last(), Some(&Access::Read(0x0000_0000)));
+
+	crate::capture::redirect(None);
+	std::fs::remove_dir_all(root).unwrap();
+}
+
+/// Downloads the payload while counting CRC errors and received packets, as requested by `limit` and `target`.
+///
+/// The returned status is the one of the last download, an error
+/// meaning the step failed.
+fn transfer(context: &mut Context, pass: usize, repetition: usize, clock_delay: f32, limit: Option<u64>, target: Option<f64>) -> Result<(Step, Result<u64, curl::Error>)> {
+	let settings = context.settings;
+	let budget   = settings.max_bytes_per_step.get_bytes() as u64;
+
+	let start = get_info(&context.handle.device, &context.counters)?;
+
+	// The duration limit applies to the whole step, not to each download.
+	let deadline = settings.seconds_per_step.map(|duration| start.instant + duration);
+
+	let mut step = Step { pass, repetition, clock_delay, ..Default::default() };
+
+	let status = loop {
+		let remaining = budget.saturating_sub(step.bytes);
+		let limit     = match target {
+			None    => limit,
+			Some(_) => Some(limit.map_or(remaining, |limit| limit.min(remaining))),
+		};
+
+		let duration = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+		let status = download(context, limit, duration);
+		let end    = get_info(&context.handle.device, &context.counters)?;
+
+		// Counters might be reset meanwhile, ex. when the driver reopens the device.
+		step.crc_errors       = end.crc_errors.saturating_sub(start.crc_errors);
+		step.packets          = end.packets.saturating_sub(start.packets);
+		step.duration         = (end.instant - start.instant).as_secs_f64();
+		step.bytes           += *status.as_ref().unwrap_or(&0);
+		step.carrier_changes  = delta(start.carrier_changes, end.carrier_changes);
+		step.link_down_events = delta(start.link_down_events, end.link_down_events);
+
+		let on_time = deadline.map_or(true, |deadline| end.instant < deadline);
+
+		let target = match (&status, target) {
+			(Ok(bytes), Some(target)) if *bytes > 0 && step.bytes < budget && on_time => target,
+			_                                                                         => break status,
+		};
+
+		// Keep collecting traffic until the CRC error rate is known to be
+		// either below or above the target at the requested confidence.
+		// Note the target is in percent, like all CRC error rates reported.
+		let (lower, upper) = confidence::interval(step.crc_errors, step.packets, settings.confidence);
+		let (lower, upper) = (100.0 * lower, 100.0 * upper);
+
+		if upper <= target || lower > target {
+			break status;
+		}
+
+		log::info!("CRC error rate within [{lower:.2e}%, {upper:.2e}%] after {} bytes, continuing", step.bytes);
+	};
+
+	Ok((step, status))
+}
+
+/// Waits for the link to run again, at most `timeout` seconds, then waits for the settle time.
+///
+/// The link state is best-effort, the settle time is waited anyway.
+fn settle(context: &Context) {
+	let settings = context.settings;
+	let deadline = Instant::now() + Duration::from_secs(settings.timeout);
+
+	loop {
+		match rtnetlink::get_link_state(&context.handle.device) {
+			Ok(link) if link.running => break,
+			Ok(link) if Instant::now() >= deadline => {
+				log::warn!("link of device {} is still {link} after {}s", context.handle.device, settings.timeout);
+				break;
+			}
+			Ok(_)      => std::thread::sleep(LINK_POLL_INTERVAL),
+			Err(error) => { log::debug!("{error}, can't wait for the link"); break }
+		}
+	}
+
+	std::thread::sleep(settings.settle);
+}
+
+/// Checks the network by downloading the first byte of the payload at the initial clock delay, then restores `clock_delay`.
+fn check_network(context: &mut Context, clock_delay: f32) -> Result<()> {
+	context.handle.set(context.reference)?;
+	settle(context);
+
+	let status = download(context, Some(1), None);
+
+	context.handle.set(clock_delay)?;
+	settle(context);
 
-		let end = get_info(device)?;
+	status?;
 
-		let mmc_rx_crc_error = end.mmc_rx_crc_error - start.mmc_rx_crc_error;
-		let rx_pkt_n         = end.rx_pkt_n         - start.rx_pkt_n;
-		let percent          = (100 * mmc_rx_crc_error) as f32 / rx_pkt_n as f32;
-		let duration         = end.instant - start.instant;
+	Ok(())
+}
 
-		println!("Done in {:.2}s; CRC error rate was {percent:.2}% ({mmc_rx_crc_error}/{rx_pkt_n})", duration.as_secs_f32());
+/// Reports the non-zero `deltas` of NIC statistics for the specified `step`.
+fn report_stats(context: &mut Context, step: &Step, deltas: &BTreeMap<String, u64>) -> Result<()> {
+	let record = serde_json::json!({
+		"pass":        step.pass,
+		"repetition":  step.repetition,
+		"clock_delay": clock_delay::convert_to_f64(step.clock_delay),
+		"deltas":      deltas,
+	});
 
-		results.push(percent);
+	if context.settings.stats_diff {
+		match context.settings.format {
+			Format::Text => for (name, delta) in deltas {
+				println!("\t{name}: +{delta}");
+			},
+			Format::Json => {
+				let mut event = record.clone();
+				event["event"] = "stats".into();
+				println!("{event}");
+			}
+		}
 	}
 
-	assert_eq!(results.len(), delays.len());
+	if let Some(stats_report) = &mut context.stats_report {
+		use std::io::Write;
+
+		writeln!(stats_report, "{record}").context("can't save NIC statistics deltas")?;
+	}
 
-	Ok(results)
+	Ok(())
 }
 
-/// Downloads the content from `url`.
+/// Downloads the payload, then returns the number of bytes received.
+///
+/// The transfer is cleanly stopped once either `limit` bytes were
+/// received or `duration` elapsed, if specified.  Only the first `limit`
+/// bytes are requested, so that servers supporting HTTP range requests
+/// send exactly this traffic volume.
+///
+/// The address of the server is pinned, and the connection is reused
+/// from one download to another if requested.
 ///
 /// This function returns an error if either the specified
 /// `speed_low_limit` or `timeout` is reached.
-fn download(url: &str, speed_low_limit: Byte, timeout: u64) -> Result<(), curl::Error> {
-	use curl::easy as curl;
+fn download(context: &mut Context, limit: Option<u64>, duration: Option<Duration>) -> Result<u64, curl::Error> {
+	let settings = context.settings;
+	let handle   = &mut context.curl;
 
-	let mut handle = curl::Easy::new();
+	// Live connections are kept whereas all options are reset.
+	handle.reset();
 
-	handle.url(url)?;
+	handle.url(&settings.url)?;
 	handle.fail_on_error(true)?;
 
+	if let Some(pinned) = &context.pinned {
+		let mut list = curl::easy::List::new();
+		list.append(pinned)?;
+		handle.resolve(list)?;
+	}
+
+	if ! settings.keep_alive {
+		handle.fresh_connect(true)?;
+		handle.forbid_reuse(true)?;
+	}
+
 	// Abort if transfer speed is < speed_low_limit/second during timeout seconds.
-	let timeout = Duration::from_secs(timeout);
+	let timeout = Duration::from_secs(settings.timeout);
 
-	handle.low_speed_limit(speed_low_limit.get_bytes() as u32)?;
+	handle.low_speed_limit(settings.speed_low_limit.get_bytes() as u32)?;
 	handle.low_speed_time(timeout)?;
 	handle.connect_timeout(timeout)?;
 
+	receive(handle, limit, duration)
+}
+
+/// Performs the transfer configured into `handle`, then returns the number of bytes received.
+///
+/// Only the first `limit` bytes are requested, the transfer is aborted
+/// if the server sends more, ie. if it doesn't support HTTP range
+/// requests.  A response ending exactly at `limit` completes normally,
+/// so that its connection can be reused.
+fn receive(handle: &mut curl::easy::Easy, limit: Option<u64>, duration: Option<Duration>) -> Result<u64, curl::Error> {
+	if let Some(limit) = limit {
+		handle.range(&format!("0-{}", limit.saturating_sub(1)))?;
+	}
+
+	let start = Instant::now();
+
+	let mut bytes   = 0;
+	let mut stopped = false;
+
 	let curl_result = {
 		let mut transfer = handle.transfer();
 
 		transfer.write_function(|data| {
-			Ok(data.len())
+			bytes += data.len() as u64;
+
+			stopped = limit.is_some_and(|limit| bytes > limit)
+			       || duration.is_some_and(|duration| start.elapsed() >= duration);
+
+			// Consuming less than the received data aborts the transfer.
+			match stopped {
+				true  => Ok(0),
+				false => Ok(data.len()),
+			}
 		})?;
 
 		transfer.perform()
 	};
 
-	curl_result?;
+	match curl_result {
+		Err(error) if error.is_write_error() && stopped => Ok(bytes),
+		Err(error) => Err(error),
+		Ok(())     => Ok(bytes),
+	}
+}
 
-	Ok(())
+#[test]
+fn test_receive () {
+	use std::io::{BufRead, BufReader, Write};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+
+	// Minimal HTTP/1.1 server supporting range requests and keep-alive.
+	let listener    = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let address     = listener.local_addr().unwrap();
+	let connections = Arc::new(AtomicUsize::new(0));
+	let accepted    = connections.clone();
+
+	std::thread::spawn(move || for stream in listener.incoming() {
+		accepted.fetch_add(1, Ordering::SeqCst);
+
+		std::thread::spawn(move || {
+			let mut stream = stream.unwrap();
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+			loop {
+				let mut length = 1_000_000;
+				let mut line   = String::new();
+
+				while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+					if let Some(end) = line.trim().strip_prefix("Range: bytes=0-") {
+						length = end.parse::<usize>().unwrap() + 1;
+					}
+					line.clear();
+				}
+
+				if line.is_empty() {
+					break;
+				}
+
+				let header = format!("HTTP/1.1 206 Partial Content\r\nContent-Length: {length}\r\nContent-Range: bytes 0-{}/1000000\r\n\r\n", length - 1);
+				stream.write_all(header.as_bytes()).unwrap();
+				stream.write_all(&vec![0; length]).unwrap();
+			}
+		});
+	});
+
+	let mut handle = curl::easy::Easy::new();
+	handle.url(&format!("http://{address}/payload")).unwrap();
+
+	assert_eq!(receive(&mut handle, Some(100_000), None).unwrap(), 100_000);
+	assert_eq!(receive(&mut handle, Some(50_000), None).unwrap(), 50_000);
+	assert_eq!(connections.load(Ordering::SeqCst), 1);
 }
 
-/// Gets current number of CRC errors and received packets.
-fn get_info(device: &str) -> Result<Info> {
-	let nic_stats = ethtool::get_nic_stats(device)?;
+/// Gets current number of CRC errors and received packets, and the link counters.
+///
+/// The link counters are best-effort, they are None if they can't be read.
+fn get_info(device: &str, counters: &Counters) -> Result<Info> {
+	let (crc_errors, packets) = counters.read(device)?;
 
-	let get = |key| nic_stats.get(key)
-	                .ok_or(anyhow!("can't find NIC statistic named \"{key}\" for device {device}"));
+	let carrier_changes = rtnetlink::get_link_state(device)
+	                      .map_err(|error| log::debug!("{error}, carrier changes are unknown"))
+	                      .ok()
+	                      .and_then(|link| link.carrier_changes);
+
+	let link_down_events = ethtool::get_link_down_events(device)
+	                       .unwrap_or_else(|error| { log::debug!("{error}, link down events are unknown"); None });
 
 	Ok(Info {
-		mmc_rx_crc_error: *get("mmc_rx_crc_error")?,
-		rx_pkt_n:         *get("rx_pkt_n")?,
-		instant:          Instant::now(),
+		crc_errors,
+		packets,
+		carrier_changes,
+		link_down_events,
+		instant: Instant::now(),
 	})
 }
 
-/// Snapshot of the number of CRC errors and received packets.
+/// Gets the increase of a link counter from `before` to `after`, 0 if unknown.
+fn delta(before: Option<u32>, after: Option<u32>) -> u64 {
+	match (before, after) {
+		(Some(before), Some(after)) => after.saturating_sub(before) as u64,
+		_                           => 0,
+	}
+}
+
+/// Snapshot of the number of CRC errors and received packets, and of the link counters.
 struct Info {
 	/// Number of CRC errors encountered in received packets.
-	mmc_rx_crc_error: u64,
+	crc_errors: u64,
 
 	/// Number of received packets.
-	rx_pkt_n:         u64,
+	packets:    u64,
+
+	/// Number of times the carrier went up or down, if reported.
+	carrier_changes: Option<u32>,
+
+	/// Number of times the link went down, ex. when autonegotiation restarted, if reported.
+	link_down_events: Option<u32>,
 
 	/// Instant when previous values were snapshotted.
-	instant:          Instant,
+	instant:    Instant,
+}
+
+/// Gets the margins, in nanoseconds, of the clock delay at `index` in `VALID_VALUES` from the edges of its passing window.
+///
+/// The passing window is the strike of consecutive valid (ie. non-NaN)
+/// `rates` containing `index`, its edges are the lowest and the highest
+/// passing clock delays.  None is returned if `index` itself failed.
+fn get_margins (rates: &[f32], index: usize) -> Option<(f32, f32)> {
+	let values = &*clock_delay::VALID_VALUES;
+	let strike = find_strikes(rates).into_iter().find(|strike| strike.start <= index && index <= strike.end)?;
+
+	Some((values[index] - values[strike.start], values[strike.end] - values[index]))
+}
+
+#[test]
+fn test_get_margins () {
+	let rates = [f32::NAN, 1.0, 1.0, 1.0, f32::NAN, 1.0, f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN];
+	let rounded = |index| get_margins(&rates, index).map(|(lower, upper)| ((lower * 100.0).round(), (upper * 100.0).round()));
+
+	assert_eq!(rounded(0), None);
+	assert_eq!(rounded(1), Some((0.0, 45.0)));
+	assert_eq!(rounded(2), Some((20.0, 25.0)));
+	assert_eq!(rounded(5), Some((0.0, 0.0)));
 }
 
 /// Returns a list of ranges representing consecutive valid (ie. non-NaN) values in specified `array`.
@@ -0,0 +1,572 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::clock_delay;
+use super::confidence;
+use super::failure::Failure;
+use anyhow::{Context, Result};
+
+/// Description of a benchmark: what was benchmarked, where and how.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Metadata {
+	/// Device name, as known by the running system.
+	pub device: String,
+
+	/// Device name, as known in device-tree.
+	pub dt_name: String,
+
+	/// GPIO connected to the RGMII GTX clock, ex. "F7 (pinctrl@44240000)".
+	pub gpio: String,
+
+	/// Device-tree nodes where the clock delay can be set permanently.
+	pub nodes: Vec<String>,
+
+	/// Model of the board, as found in device-tree.
+	pub model: String,
+
+	/// Revision of the PCB, if specified.
+	pub revision: Option<String>,
+
+	/// URL of the payload downloaded at each step.
+	pub url: String,
+
+	/// Counters of CRC errors and received packets.
+	pub counters: String,
+
+	/// Order of the clock delays within each pass, if known.
+	pub order: Option<String>,
+
+	/// Seed of the random order, if any.
+	pub seed: Option<u64>,
+
+	/// Start of the benchmark, in seconds since the Unix epoch.
+	pub timestamp: f64,
+}
+
+/// Raw measurement of a single step of a benchmark.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Step {
+	/// Number of the pass this step belongs to, starting from 1.
+	pub pass: usize,
+
+	/// Number of the repetition of this step within its pass, starting from 1.
+	pub repetition: usize,
+
+	/// Benchmarked clock delay, in nanoseconds.
+	pub clock_delay: f32,
+
+	/// Number of CRC errors encountered during this step.
+	pub crc_errors: u64,
+
+	/// Number of packets received during this step.
+	pub packets: u64,
+
+	/// Duration of this step, in seconds.
+	pub duration: f64,
+
+	/// Number of bytes downloaded during this step.
+	pub bytes: u64,
+
+	/// Why this step failed, if it did.
+	pub error: Option<String>,
+
+	/// Category of the failure of this step, if it failed and if it could be classified.
+	pub failure: Option<Failure>,
+
+	/// Number of times the carrier went up or down during this step.
+	pub carrier_changes: u64,
+
+	/// Number of times the link went down during this step, ex. when autonegotiation restarted.
+	pub link_down_events: u64,
+}
+
+impl Step {
+	/// Gets the CRC error rate of this step in percent, NaN if it failed.
+	pub fn error_rate (&self) -> f32 {
+		match self.error {
+			Some(_) => f32::NAN,
+			None    => (100 * self.crc_errors) as f32 / self.packets as f32,
+		}
+	}
+
+	/// Whether the link went down at least once during this step.
+	pub fn flapped (&self) -> bool {
+		self.carrier_changes > 0 || self.link_down_events > 0
+	}
+
+	/// Gets the upper bound of the bit error rate of this step at the specified `confidence` level, None if it failed.
+	///
+	/// The Clopper-Pearson upper bound of the CRC error rate is converted
+	/// into a bit error rate using the mean size of the received frames.
+	pub fn ber_upper_bound (&self, confidence: f64) -> Option<f64> {
+		ber_upper_bound(&[self], confidence)
+	}
+
+	/// Describes this step as JSON.
+	pub fn to_json (&self) -> serde_json::Value {
+		let mut json = serde_json::json!({
+			"pass":        self.pass,
+			"repetition":  self.repetition,
+			"clock_delay": clock_delay::convert_to_f64(self.clock_delay),
+			"crc_errors":  self.crc_errors,
+			"packets":     self.packets,
+			"duration":    self.duration,
+			"bytes":       self.bytes,
+			"error_rate":  clock_delay::convert_to_f64(self.error_rate()),
+		});
+
+		if let Some(error) = &self.error {
+			json["error"] = error.as_str().into();
+		}
+
+		if let Some(failure) = self.failure {
+			json["failure"] = failure.to_string().into();
+		}
+
+		if self.flapped() {
+			json["carrier_changes"]  = self.carrier_changes.into();
+			json["link_down_events"] = self.link_down_events.into();
+		}
+
+		json
+	}
+
+	/// Parses a step described as JSON by `to_json`.
+	fn from_json (json: &serde_json::Value) -> Option<Self> {
+		Some(Step {
+			pass:             json["pass"].as_u64()? as usize,
+			repetition:       json["repetition"].as_u64().unwrap_or(1) as usize,
+			clock_delay:      json["clock_delay"].as_f64()? as f32,
+			crc_errors:       json["crc_errors"].as_u64()?,
+			packets:          json["packets"].as_u64()?,
+			duration:         json["duration"].as_f64()?,
+			bytes:            json["bytes"].as_u64().unwrap_or_default(),
+			error:            json["error"].as_str().map(String::from),
+			failure:          json["failure"].as_str().and_then(Failure::from_name),
+			carrier_changes:  json["carrier_changes"].as_u64().unwrap_or_default(),
+			link_down_events: json["link_down_events"].as_u64().unwrap_or_default(),
+		})
+	}
+}
+
+/// Creates a step of 1000 packets, failed with a link flap if `error` is specified.
+#[cfg(test)]
+fn sample_step (pass: usize, clock_delay: f32, crc_errors: u64, error: Option<&str>) -> Step {
+	Step {
+		pass,
+		repetition:      1,
+		clock_delay,
+		crc_errors,
+		packets:         1000,
+		duration:        1.5,
+		bytes:           1_000_000,
+		error:           error.map(String::from),
+		failure:         error.map(|_| Failure::LinkDown),
+		carrier_changes: error.map_or(0, |_| 2),
+		..Default::default()
+	}
+}
+
+/// Creates results of two passes over 0, 0.3 and 0.5 ns, where 0 ns failed in the first pass only.
+#[cfg(test)]
+fn sample_results () -> Results {
+	Results {
+		metadata: Metadata { device: String::from("eth1"), nodes: vec![String::from("/soc@0/pinctrl@44240000/eth2-rgmii-0/pins2")], ..Default::default() },
+		steps:    vec![
+			sample_step(1, 0.0, 0, Some("Operation timed out")),
+			sample_step(1, 0.3, 10, None),
+			sample_step(1, 0.5, 0, None),
+			sample_step(2, 0.5, 2, None),
+			sample_step(2, 0.3, 0, None),
+			sample_step(2, 0.0, 0, None),
+		],
+	}
+}
+
+/// Gets the upper bound of the bit error rate of all `steps` together at the specified `confidence` level, None if any failed.
+///
+/// The Clopper-Pearson upper bound of the pooled CRC error rate is
+/// converted into a bit error rate using the mean size of the received
+/// frames.
+fn ber_upper_bound (steps: &[&Step], confidence: f64) -> Option<f64> {
+	let crc_errors = steps.iter().map(|step| step.crc_errors).sum::<u64>();
+	let packets    = steps.iter().map(|step| step.packets).sum::<u64>();
+	let bytes      = steps.iter().map(|step| step.bytes).sum::<u64>();
+
+	if steps.iter().any(|step| step.error.is_some()) || packets == 0 || bytes == 0 {
+		return None;
+	}
+
+	let (_, upper) = confidence::interval(crc_errors, packets, confidence);
+	let bits       = 8.0 * bytes as f64 / packets as f64;
+
+	Some(confidence::bit_error_rate(upper, bits))
+}
+
+#[test]
+fn test_ber_upper_bound () {
+	let results = sample_results();
+
+	assert_eq!(results.ber_upper_bound(0, 0.95), None);
+	assert_eq!(results.ber_upper_bound(3, 0.95), None);
+	assert!(results.ber_upper_bound(2, 0.95) < results.steps[3].ber_upper_bound(0.95));
+	assert_eq!(results.ber_upper_bound(2, 0.95), Step { crc_errors: 2, packets: 2000, bytes: 2_000_000, ..Default::default() }.ber_upper_bound(0.95));
+}
+
+/// Whether results are saved as CSV into `path`, according to its extension.
+fn is_csv (path: &Path) -> bool {
+	path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+/// Quotes a CSV `field`, doubling its quotes.
+fn escape_csv (field: &str) -> String {
+	format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+#[test]
+fn test_csv () {
+	assert_eq!(sample_results().to_csv().lines().take(3).collect::<Vec<_>>(), vec![
+		"pass,repetition,clock_delay,crc_errors,packets,duration,bytes,error_rate,failure,carrier_changes,link_down_events,error",
+		"1,1,0,0,1000,1.5,1000000,,link-down,2,0,\"Operation timed out\"",
+		"1,1,0.3,10,1000,1.5,1000000,1,,0,0,",
+	]);
+
+	assert_eq!(escape_csv("say \"hi\", twice"), "\"say \"\"hi\"\", twice\"");
+	assert!(is_csv(Path::new("eth1.CSV")) && ! is_csv(Path::new("eth1.json")));
+}
+
+/// All raw measurements of a benchmark, with its metadata.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Results {
+	/// What was benchmarked, where and how.
+	pub metadata: Metadata,
+
+	/// All steps of all passes, in the order they were performed.
+	pub steps: Vec<Step>,
+}
+
+impl Results {
+	/// Whether these results come from an adaptive benchmark, ie. short probes in pass 1 then full-length steps in pass 2.
+	pub fn is_adaptive (&self) -> bool {
+		self.metadata.order.as_deref() == Some("adaptive")
+	}
+
+	/// Gets the steps that determine the outcome of `clock_delay`.
+	///
+	/// For adaptive results, the full-length steps of pass 2 supersede
+	/// the short probes of pass 1 for the clock delays they measured.
+	fn decisive_steps (&self, clock_delay: f32) -> Vec<&Step> {
+		let steps = self.steps.iter().filter(|step| step.clock_delay == clock_delay).collect::<Vec<_>>();
+
+		match self.is_adaptive() && steps.iter().any(|step| step.pass == 2) {
+			true  => steps.into_iter().filter(|step| step.pass == 2).collect(),
+			false => steps,
+		}
+	}
+
+	/// Gets the number of passes.
+	pub fn passes (&self) -> usize {
+		self.steps.iter().map(|step| step.pass).max().unwrap_or(0)
+	}
+
+	/// Gets the direction of the sweep of the pass number `pass`: "ascending", "descending" or "random".
+	pub fn direction (&self, pass: usize) -> &'static str {
+		let mut delays = self.steps.iter().filter(|step| step.pass == pass).map(|step| step.clock_delay).collect::<Vec<_>>();
+		delays.dedup();
+
+		match () {
+			_ if delays.windows(2).all(|pair| pair[0] < pair[1]) => "ascending",
+			_ if delays.windows(2).all(|pair| pair[0] > pair[1]) => "descending",
+			_                                                    => "random",
+		}
+	}
+
+	/// Summarizes all measurements of `clock_delay`, either within `pass` or over all passes.
+	pub fn summary (&self, pass: Option<usize>, clock_delay: f32) -> Option<Summary> {
+		let steps = self.steps.iter()
+		            .filter(|step| step.clock_delay == clock_delay && pass.map_or(true, |pass| step.pass == pass))
+		            .collect::<Vec<_>>();
+
+		Summary::new(&steps)
+	}
+
+	/// Gets the mean CRC error rate of each clock delay in `VALID_VALUES` over its decisive measurements.
+	///
+	/// The rate of a clock delay is NaN if any of its measurements
+	/// failed or if it wasn't benchmarked at all.
+	pub fn rates (&self) -> Vec<f32> {
+		clock_delay::VALID_VALUES.iter().map(|clock_delay| {
+			match Summary::new(&self.decisive_steps(*clock_delay)) {
+				Some(summary) if summary.failures == 0 => summary.mean,
+				_                                      => f32::NAN,
+			}
+		}).collect()
+	}
+
+	/// Gets the upper bound of the bit error rate of the clock delay at `index` in `VALID_VALUES`, pooled over its decisive measurements.
+	///
+	/// The bound is None if this clock delay failed in any pass or if it
+	/// wasn't benchmarked at all.
+	pub fn ber_upper_bound (&self, index: usize, confidence: f64) -> Option<f64> {
+		ber_upper_bound(&self.decisive_steps(clock_delay::VALID_VALUES[index]), confidence)
+	}
+
+	/// Saves these results into `path`, as CSV if its extension is ".csv", as JSON otherwise.
+	///
+	/// Only the steps are saved as CSV, for spreadsheets: the metadata
+	/// are lost, so such a file can't be analyzed afterwards.
+	pub fn save (&self, path: &Path) -> Result<()> {
+		if is_csv(path) {
+			return std::fs::write(path, self.to_csv()).with_context(|| format!("can't write {}", path.display()));
+		}
+
+		let metadata = &self.metadata;
+
+		let json = serde_json::json!({
+			"device":    metadata.device,
+			"dt_name":   metadata.dt_name,
+			"gpio":      metadata.gpio,
+			"nodes":     metadata.nodes,
+			"model":     metadata.model,
+			"revision":  metadata.revision,
+			"url":       metadata.url,
+			"counters":  metadata.counters,
+			"order":     metadata.order,
+			"seed":      metadata.seed,
+			"timestamp": metadata.timestamp,
+			"steps":     self.steps.iter().map(Step::to_json).collect::<Vec<_>>(),
+		});
+
+		std::fs::write(path, format!("{json:#}\n")).with_context(|| format!("can't write {}", path.display()))
+	}
+
+	/// Describes all steps as CSV, one line per step after a header.
+	pub fn to_csv (&self) -> String {
+		let mut output = String::from("pass,repetition,clock_delay,crc_errors,packets,duration,bytes,error_rate,failure,carrier_changes,link_down_events,error\n");
+
+		for step in &self.steps {
+			// Writing into a String can't fail.
+			let _ = writeln!(output, "{},{},{},{},{},{},{},{},{},{},{},{}",
+			                 step.pass, step.repetition, clock_delay::convert_to_f64(step.clock_delay),
+			                 step.crc_errors, step.packets, step.duration, step.bytes,
+			                 if step.error.is_some() { String::new() } else { step.error_rate().to_string() },
+			                 step.failure.map(|failure| failure.to_string()).unwrap_or_default(),
+			                 step.carrier_changes, step.link_down_events,
+			                 step.error.as_deref().map(escape_csv).unwrap_or_default());
+		}
+
+		output
+	}
+
+	/// Loads results saved as JSON into `path`.
+	pub fn load (path: &Path) -> Result<Self> {
+		if is_csv(path) {
+			bail!("{} only holds the steps of a benchmark, save it as JSON to analyze it", path.display());
+		}
+
+		let content = std::fs::read_to_string(path).map_err(|error| anyhow!("can't read {}: {error}", path.display()))?;
+		let json    = serde_json::from_str::<serde_json::Value>(&content).with_context(|| format!("can't parse {}", path.display()))?;
+
+		Self::from_json(&json).ok_or_else(|| anyhow!("{} is not a valid benchmark result", path.display()))
+	}
+
+	/// Parses results described as JSON by `save`.
+	fn from_json (json: &serde_json::Value) -> Option<Self> {
+		let string = |key: &str| json[key].as_str().map(String::from);
+
+		let metadata = Metadata {
+			device:    string("device")?,
+			dt_name:   string("dt_name")?,
+			gpio:      string("gpio")?,
+			nodes:     json["nodes"].as_array()?.iter().map(|node| node.as_str().map(String::from)).collect::<Option<_>>()?,
+			model:     string("model")?,
+			revision:  string("revision"),
+			url:       string("url")?,
+			counters:  string("counters")?,
+			order:     string("order"),
+			seed:      json["seed"].as_u64(),
+			timestamp: json["timestamp"].as_f64()?,
+		};
+
+		let steps = json["steps"].as_array()?.iter().map(Step::from_json).collect::<Option<_>>()?;
+
+		Some(Results { metadata, steps })
+	}
+}
+
+#[test]
+fn test_rates () {
+	let results = sample_results();
+	let rates   = results.rates();
+
+	assert_eq!(rates.len(), clock_delay::VALID_VALUES.len());
+	assert!(rates[0].is_nan());
+	assert_eq!(rates[1], 0.5);
+	assert_eq!(rates[2], 0.1);
+	assert!(rates[3].is_nan());
+
+	assert!(results.steps[0].flapped());
+	assert!(! results.steps[1].flapped());
+	assert_eq!(results.passes(), 2);
+	assert!(! results.is_adaptive());
+}
+
+#[test]
+fn test_adaptive () {
+	let results = Results {
+		metadata: Metadata { order: Some(String::from("adaptive")), ..Default::default() },
+		steps:    vec![
+			sample_step(1, 0.3, 10, None),
+			sample_step(1, 0.5, 0, None),
+			sample_step(1, 0.5, 0, None),
+			sample_step(2, 0.5, 2, None),
+		],
+	};
+
+	// The full-length step of pass 2 supersedes the probes of pass 1 at 0.5 ns only.
+	let rates = results.rates();
+	assert_eq!(rates[1], 1.0);
+	assert_eq!(rates[2], 0.2);
+	assert!(results.is_adaptive());
+}
+
+#[test]
+fn test_save_load () {
+	let results = sample_results();
+	let path    = std::env::temp_dir().join(format!("stm32mp25-rgmii-gtx-clock-delay-results-{}.json", std::process::id()));
+
+	results.save(&path).unwrap();
+	assert_eq!(Results::load(&path).unwrap(), results);
+	std::fs::remove_file(&path).unwrap();
+
+	assert!(Results::load(&path.with_extension("csv")).is_err());
+	assert_eq!(Results::from_json(&serde_json::json!({})), None);
+}
+
+/// Statistics of the CRC error rates of repeated measurements of a clock delay.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Summary {
+	/// Number of measurements.
+	pub count: usize,
+
+	/// Number of failed measurements.
+	pub failures: usize,
+
+	/// Mean CRC error rate of successful measurements, in percent.
+	pub mean: f32,
+
+	/// Lowest CRC error rate of successful measurements, in percent.
+	pub min: f32,
+
+	/// Highest CRC error rate of successful measurements, in percent.
+	pub max: f32,
+
+	/// Standard deviation of CRC error rates of successful measurements, in percent.
+	pub stddev: f32,
+}
+
+impl Summary {
+	/// Summarizes the specified `steps`, None if there's none.
+	///
+	/// Statistics are NaN if all steps failed, and the standard
+	/// deviation is the sample one.
+	pub fn new (steps: &[&Step]) -> Option<Self> {
+		if steps.is_empty() {
+			return None;
+		}
+
+		let rates = steps.iter().filter(|step| step.error.is_none()).map(|step| step.error_rate()).collect::<Vec<_>>();
+		let count = rates.len() as f32;
+		let mean  = rates.iter().sum::<f32>() / count;
+
+		let stddev = match rates.len() {
+			0 => f32::NAN,
+			1 => 0.0,
+			_ => (rates.iter().map(|rate| (rate - mean).powi(2)).sum::<f32>() / (count - 1.0)).sqrt(),
+		};
+
+		Some(Summary {
+			count:    steps.len(),
+			failures: steps.len() - rates.len(),
+			mean,
+			min:      rates.iter().cloned().reduce(f32::min).unwrap_or(f32::NAN),
+			max:      rates.iter().cloned().reduce(f32::max).unwrap_or(f32::NAN),
+			stddev,
+		})
+	}
+
+	/// Describes this summary as JSON.
+	pub fn to_json (&self) -> serde_json::Value {
+		serde_json::json!({
+			"count":    self.count,
+			"failures": self.failures,
+			"mean":     clock_delay::convert_to_f64(self.mean),
+			"min":      clock_delay::convert_to_f64(self.min),
+			"max":      clock_delay::convert_to_f64(self.max),
+			"stddev":   clock_delay::convert_to_f64(self.stddev),
+		})
+	}
+}
+
+impl std::fmt::Display for Summary {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		if self.failures == self.count {
+			return write!(formatter, "{} measurement(s), all failed", self.count);
+		}
+
+		write!(formatter, "mean {:.2}%, min {:.2}%, max {:.2}%, standard deviation {:.2}% over {} measurement(s)",
+		       self.mean, self.min, self.max, self.stddev, self.count)?;
+
+		if self.failures > 0 {
+			write!(formatter, ", {} failed", self.failures)?;
+		}
+
+		Ok(())
+	}
+}
+
+#[test]
+fn test_summary () {
+	let results = sample_results();
+
+	let summary = results.summary(None, 0.3).unwrap();
+	assert_eq!((summary.count, summary.failures, summary.mean, summary.min, summary.max), (2, 0, 0.5, 0.0, 1.0));
+	assert!((summary.stddev - 0.5_f32.sqrt()).abs() < 1e-6);
+
+	let summary = results.summary(Some(1), 0.0).unwrap();
+	assert_eq!((summary.count, summary.failures), (1, 1));
+	assert!(summary.mean.is_nan());
+	assert_eq!(summary.to_string(), "1 measurement(s), all failed");
+
+	assert_eq!(results.summary(Some(3), 0.0), None);
+}
@@ -0,0 +1,183 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::ops::Range;
+
+use super::{find_strikes, get_margins};
+use crate::clock_delay;
+
+/// Lowest CRC error rate (in percent) considered by the weighted-center strategy, so error-free delays get a finite weight.
+const WEIGHT_RATE_FLOOR: f32 = 0.001;
+
+/// Strategies to select the best clock delay from the CRC error rates of all `VALID_VALUES`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub(crate) enum Strategy {
+	/// Delay closest to the center of the widest passing window
+	#[default]
+	WidestWindow,
+
+	/// Delay with the lowest CRC error rate
+	LowestError,
+
+	/// Delay closest to the center of the widest passing window, weighted by the inverse of CRC error rates
+	WeightedCenter,
+
+	/// Delay with the largest margin to the nearest edge of its passing window
+	MinMaxMargin,
+}
+
+/// Clock delay selected by a strategy, with the reasoning behind it.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Selection {
+	/// Index of the selected clock delay in `VALID_VALUES`.
+	pub index: usize,
+
+	/// Passing window containing the selected clock delay, both ends included.
+	pub window: Range<usize>,
+
+	/// Why this clock delay was selected.
+	pub reason: String,
+}
+
+impl Selection {
+	/// Gets the lowest and the highest passing clock delays of the window, in nanoseconds.
+	pub fn edges (&self) -> (f32, f32) {
+		(clock_delay::VALID_VALUES[self.window.start], clock_delay::VALID_VALUES[self.window.end])
+	}
+
+	/// Gets the margins of the selected clock delay from the lower and upper edges of the window, in nanoseconds.
+	pub fn margins (&self) -> (f32, f32) {
+		let (lower, upper) = self.edges();
+		let clock_delay    = clock_delay::VALID_VALUES[self.index];
+
+		(clock_delay - lower, upper - clock_delay)
+	}
+}
+
+impl Strategy {
+	/// Selects the best clock delay from the CRC error `rates` of all `VALID_VALUES`, NaN meaning failure.
+	pub fn select (self, rates: &[f32]) -> Option<Selection> {
+		let values  = &*clock_delay::VALID_VALUES;
+		let strikes = find_strikes(rates);
+
+		let width     = |strike: &Range<usize>| values[strike.end] - values[strike.start];
+		let mean_rate = |strike: &Range<usize>| rates[strike.start ..= strike.end].iter().sum::<f32>() / (strike.end - strike.start + 1) as f32;
+		let margin    = |index: usize| get_margins(rates, index).map(|(lower, upper)| lower.min(upper)).unwrap_or(f32::NEG_INFINITY);
+
+		// Clock delay of `strike` closest to `target`, ties are broken by the lowest rate.
+		let closest = |strike: &Range<usize>, target: f32| (strike.start ..= strike.end).min_by(|a, b| {
+			(values[*a] - target).abs().total_cmp(&(values[*b] - target).abs()).then(rates[*a].total_cmp(&rates[*b]))
+		});
+
+		// Widest passing window, ties are broken by the lowest mean rate.
+		let widest = strikes.iter().max_by(|a, b| width(a).total_cmp(&width(b)).then(mean_rate(b).total_cmp(&mean_rate(a))));
+
+		let passing = || (0 .. rates.len()).filter(|index| ! rates[*index].is_nan());
+
+		let (index, reason) = match self {
+			Strategy::WidestWindow => {
+				let strike = widest?;
+				let center = (values[strike.start] + values[strike.end]) / 2.0;
+
+				(closest(strike, center)?, format!("closest to the center ({center:.2} ns) of the widest passing window"))
+			}
+
+			Strategy::LowestError => {
+				let index = passing().min_by(|a, b| rates[*a].total_cmp(&rates[*b]).then(margin(*b).total_cmp(&margin(*a))))?;
+
+				(index, String::from("lowest CRC error rate"))
+			}
+
+			Strategy::WeightedCenter => {
+				let strike  = widest?;
+				let weights = (strike.start ..= strike.end).map(|index| (index, 1.0 / rates[index].max(WEIGHT_RATE_FLOOR))).collect::<Vec<_>>();
+				let center  = weights.iter().map(|(index, weight)| values[*index] * weight).sum::<f32>()
+				            / weights.iter().map(|(_, weight)| weight).sum::<f32>();
+
+				(closest(strike, center)?, format!("closest to the center ({center:.2} ns) of the widest passing window, weighted by the inverse of CRC error rates"))
+			}
+
+			Strategy::MinMaxMargin => {
+				let index = passing().max_by(|a, b| margin(*a).total_cmp(&margin(*b)).then(rates[*b].total_cmp(&rates[*a])).then(b.cmp(a)))?;
+
+				(index, format!("largest margin ({:.2} ns) to the nearest edge of its passing window", margin(index)))
+			}
+		};
+
+		let window = strikes.into_iter().find(|strike| strike.start <= index && index <= strike.end)?;
+
+		Some(Selection { index, window, reason })
+	}
+}
+
+impl std::fmt::Display for Strategy {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		super::format_value_enum(self, formatter)
+	}
+}
+
+#[test]
+fn test_select () {
+	let nan = f32::NAN;
+
+	// A 2-step window with few errors and a 6-step window with more errors.
+	let rates = [nan, 0.1, 0.1, nan, 0.5, 0.2, 0.2, 0.2, 0.3, 0.9, nan, nan, nan, nan];
+
+	let selection = Strategy::WidestWindow.select(&rates).unwrap();
+	assert_eq!((selection.index, selection.window.clone()), (6, 4 .. 9));
+	assert_eq!(selection.edges(), (1.0, 2.25));
+
+	let selection = Strategy::LowestError.select(&rates).unwrap();
+	assert_eq!((selection.index, selection.window), (1, 1 .. 2));
+
+	let selection = Strategy::WeightedCenter.select(&rates).unwrap();
+	assert_eq!((selection.index, selection.window), (6, 4 .. 9));
+
+	let selection = Strategy::MinMaxMargin.select(&rates).unwrap();
+	assert_eq!((selection.index, selection.window), (6, 4 .. 9));
+
+	// Weights pull the center toward the error-free side of the window.
+	let rates = [nan, 0.5, 0.5, 0.5, 0.0, 0.0, nan, nan, nan, nan, nan, nan, nan, nan];
+	assert_eq!(Strategy::WidestWindow.select(&rates).unwrap().index, 3);
+	assert_eq!(Strategy::WeightedCenter.select(&rates).unwrap().index, 4);
+
+	// Equal-width windows are ranked by their mean rate, ends included.
+	let rates = [nan, nan, nan, 0.4, 0.4, nan, 0.1, 0.5, nan, nan, nan, nan, nan, nan];
+	assert_eq!(Strategy::WidestWindow.select(&rates).unwrap().window, 6 .. 7);
+
+	let rates = [nan, 0.1, nan, 0.5, nan, nan, nan, nan, nan, nan, nan, nan, nan, nan];
+	assert_eq!(Strategy::WidestWindow.select(&rates).unwrap().window, 1 .. 1);
+
+	let rates = [nan; 14];
+	assert_eq!(Strategy::WidestWindow.select(&rates), None);
+	assert_eq!(Strategy::MinMaxMargin.select(&rates), None);
+
+	assert_eq!(Strategy::MinMaxMargin.to_string(), "min-max-margin");
+}
@@ -0,0 +1,130 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::clock_delay;
+
+/// Orders of the clock delays within each pass of a benchmark.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub(crate) enum Order {
+	/// Lowest to highest clock delay in every pass
+	Ascending,
+
+	/// Highest to lowest clock delay in every pass
+	Descending,
+
+	/// Ascending in odd passes, descending in even passes
+	#[default]
+	Alternating,
+
+	/// Shuffled in every pass
+	Random,
+}
+
+impl std::fmt::Display for Order {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		super::format_value_enum(self, formatter)
+	}
+}
+
+/// Generator of the clock delays of each pass, reproducible from its seed.
+pub(crate) struct Sweep {
+	/// Order of the clock delays within each pass.
+	order: Order,
+
+	/// State of the SplitMix64 generator used by the random order.
+	state: u64,
+}
+
+impl Sweep {
+	/// Creates a generator of clock delays in the specified `order`, `seed` being used by the random one only.
+	pub fn new (order: Order, seed: u64) -> Self {
+		Sweep { order, state: seed }
+	}
+
+	/// Gets the clock delays of the pass number `pass`, starting from 1.
+	pub fn delays (&mut self, pass: usize) -> Vec<f32> {
+		let mut delays = clock_delay::VALID_VALUES.clone();
+
+		match self.order {
+			Order::Ascending                             => (),
+			Order::Descending                            => delays.reverse(),
+			Order::Alternating if pass % 2 == 0 => delays.reverse(),
+			Order::Alternating                           => (),
+			Order::Random                                => {
+				// Fisher-Yates shuffle.
+				for index in (1 .. delays.len()).rev() {
+					let other = (self.next() % (index as u64 + 1)) as usize;
+					delays.swap(index, other);
+				}
+			}
+		}
+
+		delays
+	}
+
+	/// Gets the next pseudo-random number, see <https://prng.di.unimi.it/splitmix64.c>.
+	fn next (&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+		let mut value = self.state;
+		value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		value ^ (value >> 31)
+	}
+}
+
+#[test]
+fn test_sweep () {
+	let ascending  = clock_delay::VALID_VALUES.clone();
+	let descending = ascending.iter().cloned().rev().collect::<Vec<_>>();
+
+	let mut sweep = Sweep::new(Order::Alternating, 0);
+	assert_eq!(sweep.delays(1), ascending);
+	assert_eq!(sweep.delays(2), descending);
+	assert_eq!(sweep.delays(3), ascending);
+
+	let mut sweep = Sweep::new(Order::Descending, 0);
+	assert_eq!(sweep.delays(1), descending);
+	assert_eq!(sweep.delays(2), descending);
+
+	let mut sweep1 = Sweep::new(Order::Random, 42);
+	let mut sweep2 = Sweep::new(Order::Random, 42);
+
+	let delays = sweep1.delays(1);
+	assert_eq!(delays, sweep2.delays(1));
+	assert_ne!(delays, ascending);
+	assert_ne!(delays, sweep1.delays(2));
+
+	let mut sorted = delays.clone();
+	sorted.sort_by(f32::total_cmp);
+	assert_eq!(sorted, ascending);
+
+	assert_eq!(Order::Alternating.to_string(), "alternating");
+}
@@ -0,0 +1,430 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+
+use crate::ethtool;
+use crate::clock_delay;
+use crate::device_tree;
+
+use anyhow::{Context, Result};
+
+thread_local! {
+	/// Directory where the replayed capture was extracted, if any.
+	///
+	/// Only the thread that replays a capture is redirected, which is
+	/// the only one accessing the system, so tests running in parallel
+	/// never see each other's captures.
+	static ROOT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Gets the location of the specified system `path`.
+///
+/// When a capture is replayed, all system paths are redirected into
+/// the directory where this capture was extracted.
+pub(crate) fn path<P: AsRef<Path>>(path: P) -> PathBuf {
+	match replayed(&path) {
+		Some(path) => path,
+		None       => path.as_ref().to_path_buf(),
+	}
+}
+
+/// Gets the location of the specified system `path` within the replayed capture, if any.
+pub(crate) fn replayed<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+	let path = path.as_ref();
+
+	ROOT.with(|root| root.borrow().as_ref().map(|root| root.join(path.strip_prefix("/").unwrap_or(path))))
+}
+
+/// Redirects all system paths of the current thread into `root`, or stops redirecting them if None.
+pub(crate) fn redirect (root: Option<PathBuf>) {
+	ROOT.with(|current| *current.borrow_mut() = root);
+}
+
+/// Archives everything read from the system for the device selected by `selector` into the tarball `output`.
+///
+/// This includes the network uevent, the pinctrl debugfs entries, the
+/// relevant device-tree subtrees, the NIC statistics and the content
+/// of the memory page where the RGMII GTX clock delay lives, if /dev/mem
+/// can be mapped.  Like all
+/// other accesses, everything is read through `path`, so a replayed
+/// capture can be captured again.
+pub(crate) fn create (selector: &str, output: &Path) -> Result<()> {
+	let device = device_tree::find_device(selector)?;
+	let device = device.as_str();
+
+	let handle = std::fs::File::create(output)
+	             .map_err(|error| anyhow!("can't create {}: {error}", output.display()))?;
+
+	let mut builder = tar::Builder::new(handle);
+
+	append_file(&mut builder, format!("/sys/class/net/{device}/device/uevent"))?;
+	append_file(&mut builder, format!("/sys/class/net/{device}/address"))?;
+	append_upper_devices(&mut builder, device)?;
+
+	let dt_name = device_tree::get_name(device)?;
+	let gpio    = clock_delay::get_gpio(device, &dt_name)?;
+	let address = clock_delay::get_address(&gpio)?;
+
+	append_pinctrl(&mut builder)?;
+
+	let base = "/sys/firmware/devicetree/base";
+
+	match append_file(&mut builder, format!("{base}/model")) {
+		Ok(())     => (),
+		Err(error) => log::warn!("{error}"),
+	}
+
+	append_tree(&mut builder, format!("{base}/__symbols__"))?;
+	append_tree(&mut builder, format!("{base}/aliases"))?;
+	append_tree(&mut builder, format!("{base}/soc/{}", gpio.pinctrl))?;
+	append_tree(&mut builder, format!("{base}/soc@0/{}", gpio.pinctrl))?;
+
+	// The link is relative to "/sys/class/net/<device>/device" once extracted.
+	match device_tree::get_of_node(device) {
+		Ok(node) => {
+			append_link(&mut builder, format!("/sys/class/net/{device}/device/of_node"), format!("../../../../firmware/devicetree/base{node}"))?;
+			append_tree(&mut builder, format!("{base}{node}"))?;
+		}
+		Err(error) => log::warn!("{error}"),
+	}
+
+	let nic_stats = ethtool::get_nic_stats(device)?;
+	let mut names = nic_stats.keys().collect::<Vec<_>>();
+	names.sort();
+
+	let content = names.iter().map(|name| format!("{name} {}\n", nic_stats[*name])).collect::<String>();
+	append_data(&mut builder, format!("/ethtool/{device}"), content.as_bytes())?;
+
+	// Without /dev/mem, ex. CONFIG_STRICT_DEVMEM, the pinctrl debugfs
+	// archived above is what replays the clock delay.
+	match clock_delay::register::DevMem::mmap(&address) {
+		Ok(register) => {
+			let (page_base, content) = register.page();
+			append_data(&mut builder, format!("/dev/mem/{page_base:08x}"), &content)?;
+		}
+		Err(error) => {
+			use clock_delay::register::Register;
+
+			clock_delay::register::Debugfs::new(&gpio)?.read()
+			.with_context(|| format!("{error}, and the pinctrl debugfs doesn't report the clock delay either"))?;
+
+			log::warn!("{error}, only the clock delay reported by the pinctrl debugfs is archived");
+		}
+	}
+
+	builder.finish().with_context(|| format!("can't write {}", output.display()))?;
+
+	println!("Capture of device \"{device}\" saved into {}", output.display());
+
+	Ok(())
+}
+
+/// Extracts the tarball `archive` and redirects all further system accesses into it.
+///
+/// The extracted content is removed once the returned `Replay` is dropped.
+pub(crate) fn replay (archive: &Path) -> Result<Replay> {
+	let handle = std::fs::File::open(archive)
+	             .map_err(|error| anyhow!("can't open {}: {error}", archive.display()))?;
+
+	let directory = std::env::temp_dir().join(format!("stm32mp25-rgmii-gtx-clock-delay-{}", std::process::id()));
+
+	tar::Archive::new(handle).unpack(&directory)
+	.with_context(|| format!("can't extract {} into {}", archive.display(), directory.display()))?;
+
+	log::debug!("capture extracted into {}", directory.display());
+
+	redirect(Some(directory.clone()));
+
+	Ok(Replay { directory })
+}
+
+/// Reads the NIC statistics saved by `create` into `path`.
+pub(crate) fn read_nic_stats (path: &Path) -> Result<HashMap<String, u64>> {
+	let content = std::fs::read_to_string(path)
+	              .map_err(|error| anyhow!("can't read {}: {error}", path.display()))?;
+
+	let mut nic_stats = HashMap::new();
+
+	for line in content.lines() {
+		let mut tokens = line.rsplitn(2, ' ');
+
+		let value = tokens.next().and_then(|value| value.parse::<u64>().ok());
+		let name  = tokens.next();
+
+		match (name, value) {
+			(Some(name), Some(value)) => { nic_stats.insert(String::from(name), value); }
+			_                         => { log::warn!("malformed line \"{line}\" in {}", path.display()) }
+		}
+	}
+
+	Ok(nic_stats)
+}
+
+/// Extracted capture, removed when dropped.
+pub(crate) struct Replay {
+	/// Directory where the capture was extracted.
+	directory: PathBuf,
+}
+
+impl Drop for Replay {
+	fn drop (&mut self) {
+		redirect(None);
+
+		if let Err(error) = std::fs::remove_dir_all(&self.directory) {
+			log::warn!("{error} while removing {}", self.directory.display());
+		}
+	}
+}
+
+/// Appends the "lower_*" links of all upper devices of `device`, ie. VLAN, bridge or DSA user ports, so they can be selected too.
+///
+/// Only the links to `device`, or to its upper devices, are appended,
+/// along with the MAC address of each upper device.
+fn append_upper_devices<W: std::io::Write>(builder: &mut tar::Builder<W>, device: &str) -> Result<()> {
+	let net     = Path::new("/sys/class/net");
+	let devices = match std::fs::read_dir(path(net)) {
+		Err(error)  => { log::warn!("{error} while opening {}", net.display()); return Ok(()) }
+		Ok(entries) => entries.map_while(Result::ok).map(|entry| entry.file_name().to_string_lossy().into_owned()).collect::<Vec<_>>(),
+	};
+
+	let mut archived = vec![String::from(device)];
+
+	// Upper devices might be stacked, ex. a VLAN over a bridge.
+	loop {
+		let count = archived.len();
+
+		for upper in &devices {
+			if archived.contains(upper) {
+				continue;
+			}
+
+			let lowers = std::fs::read_dir(path(net.join(upper))).into_iter().flatten().map_while(Result::ok)
+			             .filter_map(|entry| entry.file_name().to_string_lossy().strip_prefix("lower_").map(String::from))
+			             .filter(|lower| archived.contains(lower))
+			             .collect::<Vec<_>>();
+
+			if lowers.is_empty() {
+				continue;
+			}
+
+			for lower in lowers {
+				append_link(builder, net.join(upper).join(format!("lower_{lower}")), format!("../{lower}"))?;
+			}
+
+			if let Err(error) = append_file(builder, net.join(upper).join("address")) {
+				log::warn!("{error}");
+			}
+
+			archived.push(upper.clone());
+		}
+
+		if archived.len() == count {
+			return Ok(());
+		}
+	}
+}
+
+/// Appends all "pinconf-pins" entries of "/sys/kernel/debug/pinctrl" into `builder`.
+///
+/// A missing debugfs is only reported as a warning since the device-tree is
+/// enough to find the GPIO connected to the RGMII GTX clock.
+fn append_pinctrl<W: std::io::Write>(builder: &mut tar::Builder<W>) -> Result<()> {
+	let path    = "/sys/kernel/debug/pinctrl";
+	let entries = match std::fs::read_dir(self::path(path)) {
+		Err(error)  => { log::warn!("{error} while opening {path}"); return Ok(()) }
+		Ok(entries) => { entries }
+	};
+
+	for entry in entries {
+		let entry = match entry {
+			Err(error) => { log::warn!("{error} while reading {path}"); continue }
+			Ok(entry)  => { entry }
+		};
+
+		let path = Path::new(path).join(entry.file_name()).join("pinconf-pins");
+
+		if self::path(&path).is_file() {
+			append_file(builder, path)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Appends all files found recursively from the system `directory` into `builder`.
+///
+/// A missing `directory` is silently ignored since not all systems
+/// provide the same device-tree layout.
+fn append_tree<W: std::io::Write, P: AsRef<Path>>(builder: &mut tar::Builder<W>, directory: P) -> Result<()> {
+	let directory = directory.as_ref();
+
+	if ! path(directory).is_dir() {
+		log::debug!("{} is not a directory or does not exist", directory.display());
+		return Ok(());
+	}
+
+	let entries = std::fs::read_dir(path(directory))
+	              .map_err(|error| anyhow!("can't read directory {}: {error}", directory.display()))?;
+
+	for entry in entries {
+		let entry = match entry {
+			Err(error) => { log::warn!("{error} while reading {}", directory.display()); continue }
+			Ok(entry)  => { entry }
+		};
+
+		let file_type = match entry.file_type() {
+			Err(error)    => { log::warn!("{error} while reading {}", directory.display()); continue }
+			Ok(file_type) => { file_type }
+		};
+
+		if file_type.is_dir() {
+			append_tree(builder, directory.join(entry.file_name()))?;
+		} else if file_type.is_file() {
+			append_file(builder, directory.join(entry.file_name()))?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Appends the content of the file at the system `path` into `builder`.
+///
+/// The actual content is read since the size reported by sysfs and
+/// debugfs doesn't match the size of their content.
+fn append_file<W: std::io::Write, P: AsRef<Path>>(builder: &mut tar::Builder<W>, path: P) -> Result<()> {
+	let path    = path.as_ref();
+	let content = std::fs::read(self::path(path)).map_err(|error| anyhow!("can't read {}: {error}", path.display()))?;
+
+	append_data(builder, path, &content)
+}
+
+/// Appends into `builder` a symbolic link located at `path` and pointing to `target`.
+fn append_link<W: std::io::Write, P: AsRef<Path>, Q: AsRef<Path>>(builder: &mut tar::Builder<W>, path: P, target: Q) -> Result<()> {
+	let path = path.as_ref();
+
+	log::debug!("archiving {} -> {}", path.display(), target.as_ref().display());
+
+	let mut header = tar::Header::new_gnu();
+	header.set_entry_type(tar::EntryType::Symlink);
+	header.set_size(0);
+	header.set_mode(0o777);
+
+	builder.append_link(&mut header, path.strip_prefix("/").unwrap_or(path), target)
+	.with_context(|| format!("can't archive {}", path.display()))
+}
+
+/// Appends `content` into `builder` as a regular file located at `path`.
+fn append_data<W: std::io::Write, P: AsRef<Path>>(builder: &mut tar::Builder<W>, path: P, content: &[u8]) -> Result<()> {
+	let path = path.as_ref();
+
+	log::debug!("archiving {}", path.display());
+
+	let mut header = tar::Header::new_gnu();
+	header.set_size(content.len() as u64);
+	header.set_mode(0o644);
+	header.set_cksum();
+
+	builder.append_data(&mut header, path.strip_prefix("/").unwrap_or(path), content)
+	.with_context(|| format!("can't archive {}", path.display()))
+}
+
+#[test]
+fn test_round_trip () {
+	let root    = std::env::temp_dir().join(format!("stm32mp25-rgmii-gtx-clock-delay-fake-{}", std::process::id()));
+	let archive = root.with_extension("tar");
+	let base    = "sys/firmware/devicetree/base";
+
+	// Fake system of an stm32mp257 whose ETH2 GTX clock is on PF7, with a delay of 0.75 ns.
+	let mut page = vec![0u8; 4096];
+	page[0x40 .. 0x44].copy_from_slice(&(3u32 << 28).to_ne_bytes());
+
+	let files: &[(String, &[u8])] = &[
+		(String::from("sys/class/net/eth1/device/uevent"),                   b"DRIVER=st,stm32-dwmac\nOF_NAME=eth2\n"),
+		(String::from("sys/class/net/eth1/address"),                         b"00:80:e1:42:61:1c\n"),
+		(format!("{base}/__symbols__/eth2"),                                 b"/soc@0/bus@42080000/ethernet@482c0000\0"),
+		(format!("{base}/__symbols__/gpiof"),                                b"/soc@0/pinctrl@44240000/gpio@44290000\0"),
+		(format!("{base}/soc@0/bus@42080000/ethernet@482c0000/pinctrl-0"),   &[0, 0, 0, 1]),
+		(format!("{base}/soc@0/pinctrl@44240000/compatible"),                b"st,stm32mp257-pinctrl\0"),
+		(format!("{base}/soc@0/pinctrl@44240000/eth2-rgmii-0/phandle"),      &[0, 0, 0, 1]),
+		(format!("{base}/soc@0/pinctrl@44240000/eth2-rgmii-0/pins2/pinmux"), &[0x00, 0x00, 0x57, 0x0b]),
+		(String::from("ethtool/eth1"),                                       b"mmc_rx_crc_error 3\nrx_pkt_n 100\n"),
+	];
+
+	// The Ethernet port is found from its of_node, or through a VLAN on top of it.
+	let links = [
+		("sys/class/net/eth1/device/of_node", "../../../../firmware/devicetree/base/soc@0/bus@42080000/ethernet@482c0000"),
+		("sys/class/net/vlan0/lower_eth1",    "../eth1"),
+	];
+
+	// Either /dev/mem can be mapped, or only the pinctrl debugfs reports the clock delay.
+	let sources: [(String, &[u8]); 2] = [
+		(String::from("dev/mem/44290000"),                                   &page),
+		(String::from("sys/kernel/debug/pinctrl/soc@0:pinctrl@44240000/pinconf-pins"),
+		 b"pin 87 (PF7): alternate 10 - push pull - no pull - very high speed - delay 750 (ps)\n"),
+	];
+
+	for (source, location) in std::iter::zip(sources, ["in /dev/mem", "through"]) {
+		for (path, content) in files.iter().chain([&source]) {
+			let path = root.join(path);
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::fs::write(path, content).unwrap();
+		}
+
+		for (path, target) in links {
+			let path = root.join(path);
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::os::unix::fs::symlink(target, path).unwrap();
+		}
+
+		redirect(Some(root.clone()));
+		create("eth1", &archive).unwrap();
+		redirect(None);
+
+		std::fs::remove_dir_all(&root).unwrap();
+
+		let replay = replay(&archive).unwrap();
+		let handle = clock_delay::Handle::resolve("eth1").unwrap();
+
+		assert_eq!((handle.dt_name.as_str(), handle.gpio.bank, handle.gpio.line), ("eth2", 'F', 7));
+		assert_eq!(device_tree::find_nodes(&handle.gpio), vec!["/soc@0/pinctrl@44240000/eth2-rgmii-0/pins2"]);
+		assert_eq!(handle.value.get().unwrap(), 3);
+		assert!(handle.value.location().starts_with(location));
+		assert_eq!(ethtool::get_nic_stats("eth1").unwrap()["rx_pkt_n"], 100);
+
+		assert_eq!(device_tree::find_device("vlan0").unwrap(), "eth1");
+		assert_eq!(device_tree::find_device("/soc@0/bus@42080000/ethernet@482c0000").unwrap(), "eth1");
+
+		drop(replay);
+		std::fs::remove_file(&archive).unwrap();
+	}
+}
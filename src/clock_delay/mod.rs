@@ -0,0 +1,466 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+pub(crate) mod register;
+
+use register::Register;
+use crate::Format;
+use anyhow::{Context, Result};
+
+/// Accesses and optionally modifies the clock delay of a device.
+///
+/// # Arguments
+///
+/// * `clock_delay` - Optional clock delay value to set.
+/// * `format` - Format of the output.
+pub(crate) fn access (device: &str, clock_delay: Option<f32>, format: Format) -> Result<()> {
+	let mut handle = Handle::resolve(device)?;
+
+	if let Some(clock_delay) = clock_delay {
+		handle.ensure_writable()?;
+		handle.set(clock_delay)?;
+	}
+
+	match format {
+		Format::Text => handle.print()?,
+		Format::Json => println!("{}", handle.to_json()?),
+	}
+
+	Ok(())
+}
+
+/// Clock delay of a device, resolved once to be accessed many times.
+pub(crate) struct Handle {
+	/// Device name, as known by the running system.
+	pub device: String,
+
+	/// Device name, as known in device-tree.
+	pub dt_name: String,
+
+	/// GPIO connected to the RGMII GTX clock.
+	pub gpio: Gpio,
+
+	/// Memory mapped address of the clock delay.
+	pub address: Address,
+
+	/// Clock delay value.
+	pub value: Value,
+}
+
+impl Handle {
+	/// Resolves the GPIO, the address and the mapping of the clock delay of the device selected by `selector`.
+	///
+	/// See `device_tree::find_device` for the syntax of `selector`.
+	pub fn resolve (selector: &str) -> Result<Self> {
+		let device  = crate::device_tree::find_device(selector)?;
+		let dt_name = crate::device_tree::get_name(&device)?;
+		let gpio    = get_gpio(&device, &dt_name)?;
+		let address = get_address(&gpio)?;
+		let value   = Value::open(&address, &gpio)?;
+
+		Ok(Handle { device, dt_name, gpio, address, value })
+	}
+
+	/// Fails if the clock delay can't be modified, ie. it's only accessible through the pinctrl debugfs.
+	pub fn ensure_writable (&self) -> Result<()> {
+		if ! self.value.is_writable() {
+			bail!("RGMII GTX clock delay of device \"{}\" is only readable {}, modifying it requires /dev/mem \
+			       (ex. a kernel without CONFIG_STRICT_DEVMEM)", self.device, self.value.location());
+		}
+
+		Ok(())
+	}
+
+	/// Sets the clock delay, in nanoseconds.
+	pub fn set (&mut self, clock_delay: f32) -> Result<()> {
+		self.value.set(clock_delay)
+	}
+
+	/// Prints how the clock delay was resolved and its current value.
+	pub fn print (&self) -> Result<()> {
+		println!("device named \"{}\" is known as \"{}\" in device-tree", self.device, self.dt_name);
+		println!("↳ its RGMII GTX clock is connected to GPIO {}", self.gpio);
+		println!("  ↳ its delay can be accessed at address {} {}", self.address, self.value.location());
+		println!("    ↳ its value is {:#x} ({} nanoseconds)", self.value.get()?, self.value.get_as_ns()?);
+
+		Ok(())
+	}
+
+	/// Describes how the clock delay was resolved and its current value, as JSON.
+	pub fn to_json (&self) -> Result<serde_json::Value> {
+		Ok(serde_json::json!({
+			"device":  self.device,
+			"dt_name": self.dt_name,
+			"gpio": {
+				"bank":    self.gpio.bank.to_string(),
+				"line":    self.gpio.line,
+				"pinctrl": self.gpio.pinctrl,
+			},
+			"address": {
+				"base":       self.address.base,
+				"bit_offset": self.address.offset,
+				"bit_width":  4,
+				"location":   self.value.location(),
+			},
+			"value":       self.value.get()?,
+			"clock_delay": convert_to_f64(self.value.get_as_ns()?),
+			"nodes":       crate::device_tree::find_nodes(&self.gpio),
+		}))
+	}
+}
+
+/// Gets the `gpio` of the specified `device`, known as `dt_name` in device-tree.
+///
+/// The pinctrl debugfs is used if available, otherwise the GPIO is
+/// found from the device-tree only.
+pub(crate) fn get_gpio (device: &str, dt_name: &str) -> Result<Gpio> {
+	match get_gpio_from_debugfs(dt_name) {
+		Ok(gpio)   => Ok(gpio),
+		Err(error) => {
+			log::warn!("{error}, falling back to device-tree");
+			crate::device_tree::get_gpio(device)
+		}
+	}
+}
+
+/// Gets the `gpio` of the specified device-tree node from "/sys/kernel/debug/pinctrl".
+fn get_gpio_from_debugfs (dt_name: &str) -> Result<Gpio> {
+	use std::io::BufRead;
+
+	let root    = crate::capture::path("/sys/kernel/debug/pinctrl/");
+	let path    = root.display();
+	let entries = std::fs::read_dir(&root)
+	              .map_err(|error| anyhow!("can't read directory {path}: {error}"))?;
+
+	let message = "can't find the GPIO connected to the RGMII GTX clock";
+
+	for entry in entries {
+		let entry = match entry {
+			Err(error) => { log::warn!("{error} while reading {path}"); continue }
+			Ok(entry)  => { entry }
+		};
+
+		let file_name = entry.file_name();
+		let file_name = file_name.to_string_lossy();
+
+		let mut tokens = file_name.split('@');
+
+		let prefix_is_compatible = match tokens.next() {
+			Some("soc:pinctrl") => true,
+			Some("soc")         => tokens.next() == Some("0:pinctrl"),
+			_                   => false,
+		};
+
+		if ! prefix_is_compatible {
+			continue;
+		}
+
+		let pinctrl = format!("pinctrl@{}", tokens.next().unwrap_or("???"));
+
+		let mut path = entry.path();
+		path.push("pinconf-pins");
+
+		let handle = std::fs::File::open(path).context("can't open {path}")?;
+		let reader = std::io::BufReader::new(handle);
+		let needle = format!("{}_RGMII_GTX_CLK", dt_name.to_uppercase());
+
+		for line in reader.lines().map_while(Result::ok) {
+			if ! line.contains(&needle) {
+				continue;
+			}
+
+			let mut tokens = line.split('(');
+			let     tokens = tokens.nth(1).ok_or(anyhow!(message))?;
+			let mut tokens = tokens.split(')');
+			let     token  = tokens.next().ok_or(anyhow!(message))?;
+			let mut tokens = token.chars();
+
+			let magic = tokens.next();
+			let bank  = tokens.next();
+			let line  = tokens.as_str().parse::<u8>().ok();
+
+			if magic != Some('P') || bank.is_none() || line.is_none() {
+				continue;
+			}
+
+			return Ok(Gpio {
+				bank: bank.unwrap(),
+				line: line.unwrap(),
+				pinctrl,
+			});
+		}
+	}
+
+	bail!(message)
+}
+
+/// Gets the memory mapped address of the specified `gpio`.
+pub(crate) fn get_address (gpio: &Gpio) -> Result<Address> {
+	let path = crate::capture::path(format!("/sys/firmware/devicetree/base/__symbols__/gpio{}", gpio.bank.to_lowercase()));
+	let path = std::fs::read_to_string(&path).map_err(|error| anyhow!("can't read {}: {error}", path.display()))?;
+	let path = path.trim_end_matches('\0');
+
+	let anyhow = anyhow!("can't find the address of the GPIO connected to the RGMII GTX clock");
+
+	match path.split('@').next_back() {
+		None          => Err(anyhow),
+		Some(address) => {
+			match usize::from_str_radix(address, 16) {
+				Ok(address) => Ok(Address { base: address + 0x40, offset: gpio.line * 4 }),
+				Err(_)      => Err(anyhow),
+			}
+		}
+	}
+}
+
+/// Clock delay value held in a register.
+///
+/// This structure handles the bitfield within the register, the
+/// actual accesses are performed by the underlying `Register`.
+pub(crate) struct Value {
+	register: Box<dyn Register>,
+	offset:   u8,
+}
+
+impl Value {
+	/// Create a clock delay value held at `offset` in the specified `register`.
+	pub fn new (register: Box<dyn Register>, offset: u8) -> Self {
+		assert!(offset <= 28); // Not yet supported.
+		Value { register, offset }
+	}
+
+	/// Open the clock delay value at the specified `address` for the specified `gpio`.
+	///
+	/// The register is memory mapped from /dev/mem, unless this is
+	/// not permitted (ex. `CONFIG_STRICT_DEVMEM`), in which case the
+	/// pinctrl debugfs is used instead.
+	pub fn open (address: &Address, gpio: &Gpio) -> Result<Self> {
+		let register: Box<dyn Register> = match register::DevMem::mmap(address) {
+			Ok(register) => Box::new(register),
+			Err(error)   => {
+				log::warn!("{error}, falling back to pinctrl debugfs");
+				Box::new(register::Debugfs::new(gpio)?)
+			}
+		};
+
+		Ok(Value::new(register, address.offset))
+	}
+
+	/// Describe where the clock delay value is accessed.
+	pub fn location (&self) -> String {
+		self.register.location()
+	}
+
+	/// Whether the clock delay value can be modified.
+	pub fn is_writable (&self) -> bool {
+		self.register.is_writable()
+	}
+
+	/// Get the clock delay value in nanoseconds.
+	pub fn get_as_ns (&self) -> Result<f32> {
+		convert_to_ns(self.get()?)
+	}
+
+	/// Get the clock delay value.
+	pub fn get (&self) -> Result<u32> {
+		let value = self.register.read()?;
+		Ok((value >> self.offset) & 0xF)
+	}
+
+	/// Set the clock delay value.
+	///
+	/// The value is read back to ensure the write actually landed.
+	pub fn set (&mut self, clock_delay: f32) -> Result<()> {
+		let bits  = convert_to_bits(clock_delay)?;
+		let value = self.register.read()?;
+		let value = (value & !(0xF << self.offset)) | (bits << self.offset);
+
+		self.register.write(value)?;
+
+		let actual = self.get()?;
+		if actual != bits {
+			bail!("RGMII GTX clock delay was not written: expected {bits:#x}, read back {actual:#x}");
+		}
+
+		Ok(())
+	}
+}
+
+#[test]
+fn test_value () {
+	use register::{Mock, Access};
+
+	let mock      = Mock::new(0xA5A5_A5A5);
+	let accesses  = mock.accesses();
+	let mut value = Value::new(Box::new(mock), 28);
+
+	assert_eq!(value.get().unwrap(), 0xA);
+	assert_eq!(value.get_as_ns().unwrap(), 2.5);
+
+	value.set(0.75).unwrap();
+
+	assert_eq!(value.get().unwrap(), 0x3);
+	assert_eq!(*accesses.borrow(), vec![
+		Access::Read(0xA5A5_A5A5),
+		Access::Read(0xA5A5_A5A5),
+		Access::Read(0xA5A5_A5A5),
+		Access::Write(0x35A5_A5A5),
+		Access::Read(0x35A5_A5A5),
+		Access::Read(0x35A5_A5A5),
+	]);
+
+	assert!(value.set(1.2).is_err());
+	assert_eq!(accesses.borrow().len(), 6);
+
+	let mut value = Value::new(Box::new(Mock::stuck(0x0000_0000)), 0);
+	assert!(value.set(0.75).is_err());
+}
+
+/// Converts specified clock delay from `f32` to `u32` (interpreted as bitfields).
+pub(crate) fn convert_to_bits(ns: f32) -> Result<u32> {
+	// floating point literals not allowed anymore in patterns:
+	// https://github.com/rust-lang/rust/issues/41620b
+	if ns == 0.3 {
+		Ok(1)
+	} else if ns >= 0.0
+	       && ns != 0.25
+	       && ns <= 3.25
+               && ns % 0.25 == 0.0 {
+		Ok((ns * 4.0) as u32)
+	} else {
+		bail!("invalid RGMII clock/data delay: {ns} (in nanoseconds)")
+	}
+}
+
+#[test]
+fn test_convert_bits () {
+	assert_eq!(convert_to_bits(0.0).unwrap(),   0);
+	assert_eq!(convert_to_bits(0.3).unwrap(),   1);
+	assert_eq!(convert_to_bits(0.5).unwrap(),   2);
+	assert_eq!(convert_to_bits(0.75).unwrap(),  3);
+	assert_eq!(convert_to_bits(1.0).unwrap(),   4);
+	assert_eq!(convert_to_bits(1.25).unwrap(),  5);
+	assert_eq!(convert_to_bits(1.5).unwrap(),   6);
+	assert_eq!(convert_to_bits(1.75).unwrap(),  7);
+	assert_eq!(convert_to_bits(2.0).unwrap(),   8);
+	assert_eq!(convert_to_bits(2.25).unwrap(),  9);
+	assert_eq!(convert_to_bits(2.5).unwrap(),  10);
+	assert_eq!(convert_to_bits(2.75).unwrap(), 11);
+	assert_eq!(convert_to_bits(3.0).unwrap(),  12);
+	assert_eq!(convert_to_bits(3.25).unwrap(), 13);
+	assert!(convert_to_bits(1.2).is_err());
+	assert!(convert_to_bits(0.25).is_err());
+}
+
+/// Converts specified clock delay from `u32` (interpreted as bitfields) to nanoseconds.
+pub(crate) fn convert_to_ns(bits: u32) -> Result<f32> {
+	match bits {
+		0          => Ok(0.0),
+		1          => Ok(0.3),
+		x @ 2..=12 => Ok(0.25 * x as f32),
+		13..=16    => Ok(3.25),
+		x          => bail!("invalid RGMII clock/data delay: {x:0x}")
+	}
+}
+
+#[test]
+fn test_convert_ns () {
+	for bits in 0..=13 {
+		assert_eq!(convert_to_bits(convert_to_ns(bits).unwrap()).unwrap(), bits);
+	}
+	assert_eq!(convert_to_ns(14).unwrap(), 3.25);
+	assert_eq!(convert_to_ns(15).unwrap(), 3.25);
+	assert!(convert_to_ns(17).is_err());
+}
+
+/// Converts specified clock delay, CRC error rate or any other statistic from `f32` to `f64` without introducing extra decimals, ex. 0.3 -> 0.3 and not 0.30000001192092896.
+pub(crate) fn convert_to_f64(value: f32) -> f64 {
+	value.to_string().parse().unwrap_or(value as f64)
+}
+
+lazy_static! {
+	/// List of all valid clock delay values.
+	pub(crate) static ref VALID_VALUES: Vec<f32> = {
+		let mut valid_values = vec![0 as f32, 0.3];
+		valid_values.append(&mut (2..=13).map(|x| x as f32 * 0.25).collect());
+		valid_values
+	};
+}
+
+/// Parses the specified clock delay `value` from `&str` into `f32`.
+///
+/// This function also checks that the parsed value is valid.
+pub(crate) fn parser (value: &str) -> Result<f32> {
+	match value.parse::<f32>() {
+		Err(error) => bail!("not a floating point value ({error})"),
+		Ok(value)  => {
+			if VALID_VALUES.contains(&value) {
+				Ok(value)
+			} else {
+				bail!("must be one of {:?}", *VALID_VALUES)
+			}
+		}
+	}
+}
+
+/// GPIO information.
+#[derive(Debug)]
+pub(crate) struct Gpio {
+	/// Bank to which the pin belongs, ex. GPIOA.
+	pub bank: char,
+
+	/// Line of the pin within the bank.
+	pub line: u8,
+
+	/// Name of the pin controler.
+	pub pinctrl: String,
+}
+
+/// Memory mapped address of a GPIO.
+#[derive(Debug)]
+pub(crate) struct Address {
+	/// Base address of the GPIO.
+	pub base:   usize,
+
+	/// Offset from the base address.
+	pub offset: u8,
+}
+
+impl std::fmt::Display for Gpio {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		write!(formatter, "{}{} ({})", self.bank, self.line, self.pinctrl)
+	}
+}
+
+impl std::fmt::Display for Address {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		write!(formatter, "{:#x} (bits {}-{})", self.base, self.offset, self.offset + 4)
+	}
+}
@@ -0,0 +1,296 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::path::PathBuf;
+
+use super::{Address, Gpio};
+use anyhow::Result;
+
+/// Access to the 32-bit register holding a clock delay value.
+pub(crate) trait Register {
+	/// Read the whole register.
+	fn read (&self) -> Result<u32>;
+
+	/// Write the whole register.
+	fn write (&mut self, value: u32) -> Result<()>;
+
+	/// Describe where the register is accessed, ex. "in /dev/mem".
+	fn location (&self) -> String;
+
+	/// Whether the register can be written at all.
+	fn is_writable (&self) -> bool {
+		true
+	}
+}
+
+/// Memory mapping of a register through /dev/mem.
+///
+/// This structure handles alignment and unmapping requirements.
+#[derive(Debug)]
+pub(crate) struct DevMem {
+	address:   *mut u32,
+	mmap_addr: *mut libc::c_void,
+	mmap_len:  usize,
+	page_base: usize,
+}
+
+impl DevMem {
+	/// Create a memory mapping for the specified `address`.
+	///
+	/// This function handles all alignment constraints.
+	pub fn mmap (address: &Address) -> Result<Self> {
+		use nix::unistd::{sysconf, SysconfVar};
+		use nix::sys::mman::{mmap, ProtFlags, MapFlags};
+		use std::os::unix::io::AsRawFd;
+
+		let prot_flags  = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+		let page_size   = sysconf(SysconfVar::PAGE_SIZE)?.unwrap_or(4096) as usize;
+		let length      = std::num::NonZeroUsize::new(page_size).unwrap();
+		let page_base   = (address.base & !(page_size - 1)) as libc::off_t;
+		let page_offset = address.base & (page_size - 1);
+
+		// When replaying a capture, the page is read from a private
+		// copy of the archived content instead of /dev/mem.
+		let (path, map_flags, file_offset) = match crate::capture::replayed(format!("/dev/mem/{page_base:08x}")) {
+			None       => (PathBuf::from("/dev/mem"), MapFlags::MAP_SHARED, page_base),
+			Some(path) => (path, MapFlags::MAP_PRIVATE, 0),
+		};
+
+		let handle = std::fs::OpenOptions::new().read(true).write(map_flags == MapFlags::MAP_SHARED).open(&path)
+		             .map_err(|error| anyhow!("can't open {}: {error}", path.display()))?;
+
+		let address = unsafe {
+			mmap(None, length, prot_flags, map_flags, handle.as_raw_fd(), file_offset)
+			.map_err(|error| anyhow!("can't mmap page {page_base:0x}: {error}"))?
+		};
+
+		log::debug!("mmaped address = {address:?}");
+
+		Ok(DevMem {
+			address:   unsafe { address.add(page_offset) } as *mut u32,
+			mmap_addr: address,
+			mmap_len:  usize::from(length),
+			page_base: page_base as usize,
+		})
+	}
+
+	/// Get the base address and the content of the whole mapped memory page.
+	pub fn page (&self) -> (usize, Vec<u8>) {
+		let words = self.mmap_addr as *const u32;
+
+		let content = (0..self.mmap_len / 4)
+		              .flat_map(|index| unsafe { std::ptr::read_volatile(words.add(index)) }.to_ne_bytes())
+		              .collect();
+
+		(self.page_base, content)
+	}
+}
+
+impl Register for DevMem {
+	fn read (&self) -> Result<u32> {
+		Ok(unsafe { std::ptr::read_volatile(self.address) })
+	}
+
+	fn write (&mut self, value: u32) -> Result<()> {
+		unsafe { std::ptr::write_volatile(self.address, value) }
+		Ok(())
+	}
+
+	fn location (&self) -> String {
+		String::from("in /dev/mem")
+	}
+}
+
+impl Drop for DevMem {
+	fn drop (&mut self) {
+		unsafe { nix::sys::mman::munmap(self.mmap_addr, self.mmap_len).unwrap() }
+	}
+}
+
+/// Read-only access to a clock delay through the pinctrl debugfs.
+///
+/// The STM32 pinctrl driver reports the delay of each pin as "delay
+/// <value> (ps)" in its "pinconf-pins" entry.  There's no way to
+/// modify this delay through debugfs: "pinmux-select" only switches
+/// between the pin groups of a function and pin configurations can't
+/// be written anymore, so this access is read-only by design and the
+/// commands that modify the clock delay refuse to start with it.
+#[derive(Debug)]
+pub(crate) struct Debugfs {
+	/// Path to the "pinconf-pins" entry of the pin controller.
+	path:   PathBuf,
+
+	/// Pin name as reported by the pin controller, ex. "(PF7)".
+	needle: String,
+
+	/// Offset of the clock delay within the synthesized register.
+	offset: u8,
+}
+
+impl Debugfs {
+	/// Create a debugfs access for the specified `gpio`.
+	pub fn new (gpio: &Gpio) -> Result<Self> {
+		let root    = crate::capture::path("/sys/kernel/debug/pinctrl/");
+		let entries = std::fs::read_dir(&root)
+		              .map_err(|error| anyhow!("can't read directory {}: {error}", root.display()))?;
+
+		let suffix = format!(":{}", gpio.pinctrl);
+
+		for entry in entries.map_while(Result::ok) {
+			if entry.file_name().to_string_lossy().ends_with(&suffix) {
+				return Ok(Debugfs {
+					path:   entry.path().join("pinconf-pins"),
+					needle: format!("(P{}{})", gpio.bank, gpio.line),
+					offset: gpio.line * 4,
+				});
+			}
+		}
+
+		bail!("can't find {} in {}", gpio.pinctrl, root.display())
+	}
+}
+
+impl Register for Debugfs {
+	fn read (&self) -> Result<u32> {
+		let content = std::fs::read_to_string(&self.path)
+		              .map_err(|error| anyhow!("can't read {}: {error}", self.path.display()))?;
+
+		let line = content.lines().find(|line| line.contains(&self.needle))
+		           .ok_or(anyhow!("can't find pin {} in {}", self.needle, self.path.display()))?;
+
+		let picoseconds = parse_delay(line)
+		                  .ok_or(anyhow!("can't find the delay of pin {} in {}", self.needle, self.path.display()))?;
+
+		let bits = super::convert_to_bits(picoseconds as f32 / 1000.0)?;
+
+		Ok(bits << self.offset)
+	}
+
+	fn write (&mut self, _value: u32) -> Result<()> {
+		bail!("pinctrl debugfs doesn't allow to modify the clock delay, /dev/mem is required")
+	}
+
+	fn location (&self) -> String {
+		format!("through {} (read-only)", self.path.display())
+	}
+
+	fn is_writable (&self) -> bool {
+		false
+	}
+}
+
+/// Parses the delay, in picoseconds, from a "pinconf-pins" `line`.
+fn parse_delay (line: &str) -> Option<u32> {
+	let mut tokens = line.split(|char: char| char.is_whitespace() || char == ',');
+
+	tokens.find(|token| *token == "delay")?;
+	tokens.find(|token| ! token.is_empty())?.parse::<u32>().ok()
+}
+
+#[test]
+fn test_parse_delay () {
+	assert_eq!(parse_delay("pin 87 (PF7): alternate 10 - push pull - no pull - very high speed - output delay 750 (ps)"), Some(750));
+	assert_eq!(parse_delay("pin 87 (PF7): alternate 10 - push pull - no pull - very high speed - delay 0 (ps)"), Some(0));
+	assert_eq!(parse_delay("pin 87 (PF7): alternate 10 - push pull - no pull - very high speed"), None);
+}
+
+#[test]
+fn test_debugfs () {
+	let path = std::env::temp_dir().join(format!("stm32mp25-rgmii-gtx-clock-delay-pinconf-pins-{}", std::process::id()));
+	std::fs::write(&path, "pin 87 (PF7): alternate 10 - push pull - no pull - very high speed - delay 750 (ps)\n").unwrap();
+
+	let mut register = Debugfs { path: path.clone(), needle: String::from("(PF7)"), offset: 28 };
+
+	assert_eq!(register.read().unwrap(), 3 << 28);
+	assert!(! register.is_writable());
+	assert!(register.write(0).is_err());
+
+	std::fs::remove_file(path).unwrap();
+}
+
+/// Access to a register, as recorded by `Mock`.
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+pub(crate) enum Access {
+	Read(u32),
+	Write(u32),
+}
+
+/// In-memory register that records every access.
+#[cfg(test)]
+pub(crate) struct Mock {
+	value:    std::cell::Cell<u32>,
+	writable: bool,
+	accesses: std::rc::Rc<std::cell::RefCell<Vec<Access>>>,
+}
+
+#[cfg(test)]
+impl Mock {
+	/// Create an in-memory register initialized to `value`.
+	pub fn new (value: u32) -> Self {
+		Mock {
+			value:    std::cell::Cell::new(value),
+			writable: true,
+			accesses: Default::default(),
+		}
+	}
+
+	/// Create an in-memory register stuck at `value`, ie. writes are recorded but ignored.
+	pub fn stuck (value: u32) -> Self {
+		Mock { writable: false, ..Mock::new(value) }
+	}
+
+	/// Get the list of accesses, still recorded once this register is moved.
+	pub fn accesses (&self) -> std::rc::Rc<std::cell::RefCell<Vec<Access>>> {
+		self.accesses.clone()
+	}
+}
+
+#[cfg(test)]
+impl Register for Mock {
+	fn read (&self) -> Result<u32> {
+		self.accesses.borrow_mut().push(Access::Read(self.value.get()));
+		Ok(self.value.get())
+	}
+
+	fn write (&mut self, value: u32) -> Result<()> {
+		self.accesses.borrow_mut().push(Access::Write(value));
+
+		if self.writable {
+			self.value.set(value);
+		}
+
+		Ok(())
+	}
+
+	fn location (&self) -> String {
+		String::from("in memory")
+	}
+}
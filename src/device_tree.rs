@@ -34,21 +34,177 @@ use std::io::Read;
 use crate::clock_delay::Gpio;
 use anyhow::Result;
 
+/// Finds the name of the network device selected by `selector`.
+///
+/// The `selector` is either a device name (ex. "eth1"), a MAC address
+/// (ex. "00:80:e1:42:61:1c"), a device-tree path (ex.
+/// "/soc@0/bus@42080000/eth2@482d0000") or a device-tree alias (ex.
+/// "ethernet1").  Upper devices, like VLAN, bridge or DSA user ports,
+/// are resolved to the underlying Ethernet port.
+pub(crate) fn find_device (selector: &str) -> Result<String> {
+	let device = if crate::capture::path(format!("/sys/class/net/{selector}")).exists() {
+		String::from(selector)
+	} else if let Some(mac) = parse_mac(selector) {
+		find_device_by(|device| read_mac(device) == Some(mac))
+		.ok_or(anyhow!("can't find any network device with MAC address {selector}"))?
+	} else {
+		let path = resolve_alias(selector)?;
+		find_device_by(|device| get_of_node(device).ok().as_deref() == Some(path.as_str()))
+		.ok_or(anyhow!("can't find any network device for device-tree node {path}"))?
+	};
+
+	let lower = find_lower_device(&device)?;
+	if lower != device {
+		log::info!("device named \"{device}\" is backed by \"{lower}\"");
+	}
+
+	Ok(lower)
+}
+
+/// Finds the first network device that matches `predicate`.
+fn find_device_by<F: Fn(&str) -> bool> (predicate: F) -> Option<String> {
+	let path = crate::capture::path("/sys/class/net");
+
+	std::fs::read_dir(path).ok()?
+	.map_while(Result::ok)
+	.map(|entry| entry.file_name().to_string_lossy().into_owned())
+	.find(|device| predicate(device))
+}
+
+/// Finds the Ethernet port underlying the upper `device`, ie. VLAN, bridge or DSA user port.
+///
+/// This function actually follows "/sys/class/net/`device`/lower_*"
+/// recursively, keeping only ports described in device-tree.
+fn find_lower_device (device: &str) -> Result<String> {
+	let path = crate::capture::path(format!("/sys/class/net/{device}"));
+
+	let lowers = match std::fs::read_dir(&path) {
+		Err(error)   => bail!("can't read directory {}: {error}", path.display()),
+		Ok(read_dir) => read_dir.map_while(Result::ok)
+		                .filter_map(|entry| entry.file_name().to_string_lossy().strip_prefix("lower_").map(String::from))
+		                .collect::<Vec<_>>(),
+	};
+
+	if lowers.is_empty() {
+		return Ok(String::from(device));
+	}
+
+	let mut candidates = Vec::new();
+
+	for lower in lowers {
+		match find_lower_device(&lower) {
+			Ok(lower)  => if get_name(&lower).is_ok() && ! candidates.contains(&lower) { candidates.push(lower) },
+			Err(error) => log::warn!("{error}"),
+		}
+	}
+
+	match candidates.len() {
+		0 => bail!("device named \"{device}\" isn't backed by any Ethernet port described in device-tree"),
+		1 => Ok(candidates.remove(0)),
+		_ => bail!("device named \"{device}\" is backed by several Ethernet ports ({}), please select one", candidates.join(", ")),
+	}
+}
+
+/// Resolves the device-tree `path`, which might start with an alias.
+///
+/// This function actually parses "/sys/firmware/devicetree/base/aliases".
+fn resolve_alias (path: &str) -> Result<String> {
+	if path.starts_with('/') {
+		return Ok(String::from(path.trim_end_matches('/')));
+	}
+
+	let mut tokens = path.splitn(2, '/');
+	let alias      = tokens.next().unwrap_or_default();
+	let suffix     = tokens.next();
+
+	let alias_path = crate::capture::path(format!("/sys/firmware/devicetree/base/aliases/{alias}"));
+	let alias_path = std::fs::read_to_string(&alias_path)
+	                 .map_err(|_| anyhow!("\"{path}\" is neither a device name, a MAC address, a device-tree path nor a device-tree alias"))?;
+	let alias_path = alias_path.trim_end_matches('\0');
+
+	match suffix {
+		None         => Ok(String::from(alias_path)),
+		Some(suffix) => Ok(format!("{alias_path}/{}", suffix.trim_end_matches('/'))),
+	}
+}
+
+/// Gets the device-tree path of the specified `device`, ex. "/soc@0/bus@42080000/eth2@482d0000".
+///
+/// This function actually follows "/sys/class/net/`device`/device/of_node".
+pub(crate) fn get_of_node (device: &str) -> Result<String> {
+	let base = crate::capture::path("/sys/firmware/devicetree/base");
+	let path = crate::capture::path(format!("/sys/class/net/{device}/device/of_node"));
+	let node = std::fs::canonicalize(&path).map_err(|error| anyhow!("can't resolve {}: {error}", path.display()))?;
+
+	match node.strip_prefix(&base) {
+		Ok(node) => Ok(format!("/{}", node.display())),
+		Err(_)   => bail!("{} doesn't point into {}", path.display(), base.display()),
+	}
+}
+
+/// Reads the MAC address of the specified `device`.
+fn read_mac (device: &str) -> Option<[u8; 6]> {
+	let path = crate::capture::path(format!("/sys/class/net/{device}/address"));
+	parse_mac(std::fs::read_to_string(path).ok()?.trim())
+}
+
+/// Parses a MAC address, ex. "00:80:e1:42:61:1c".
+fn parse_mac (value: &str) -> Option<[u8; 6]> {
+	let mut mac    = [0u8; 6];
+	let mut tokens = value.split([':', '-']);
+
+	for byte in mac.iter_mut() {
+		let token = tokens.next()?;
+		if token.len() != 2 {
+			return None;
+		}
+		*byte = u8::from_str_radix(token, 16).ok()?;
+	}
+
+	match tokens.next() {
+		None    => Some(mac),
+		Some(_) => None,
+	}
+}
+
+#[test]
+fn test_parse_mac () {
+	assert_eq!(parse_mac("00:80:e1:42:61:1c"),    Some([0x00, 0x80, 0xe1, 0x42, 0x61, 0x1c]));
+	assert_eq!(parse_mac("00-80-E1-42-61-1C"),    Some([0x00, 0x80, 0xe1, 0x42, 0x61, 0x1c]));
+	assert_eq!(parse_mac("00:80:e1:42:61"),       None);
+	assert_eq!(parse_mac("00:80:e1:42:61:1c:00"), None);
+	assert_eq!(parse_mac("0:80:e1:42:61:1c"),     None);
+	assert_eq!(parse_mac("eth1"),                 None);
+}
+
 /// Gets the device-tree name of the specified `device`.
 ///
 /// For example, a network device can be named "eth0" by the running
 /// system, but its device-tree name might be "eth2".
 ///
-/// This function actually parses "/sys/class/net/`device`/device/uevent".
+/// This function actually parses "/sys/class/net/`device`/device/uevent",
+/// or follows "/sys/class/net/`device`/device/of_node" if there's no
+/// OF_NAME entry in the former.
 pub(crate) fn get_name (device: &str) -> Result<String> {
+	match get_name_from_uevent(device) {
+		Ok(name)   => Ok(name),
+		Err(error) => {
+			log::debug!("{error}, falling back to of_node");
+			get_name_from_of_node(device).map_err(|error2| anyhow!("{error} and {error2}"))
+		}
+	}
+}
+
+/// Gets the device-tree name of the specified `device` from its uevent.
+fn get_name_from_uevent (device: &str) -> Result<String> {
 	use std::io::BufRead;
 
-	let path   = format!("/sys/class/net/{device}/device/uevent");
+	let path   = crate::capture::path(format!("/sys/class/net/{device}/device/uevent"));
 	let handle = std::fs::File::open(&path)
-	             .map_err(|error| anyhow!("can't open {path}: {error}"))?;
+	             .map_err(|error| anyhow!("can't open {}: {error}", path.display()))?;
 
 	let reader = std::io::BufReader::new(handle);
-	let error  = anyhow!("can't find OF_NAME entry in {path}");
+	let error  = anyhow!("can't find OF_NAME entry in {}", path.display());
 
 	for line in reader.lines().map_while(Result::ok) {
 		let mut tokens = line.split('=');
@@ -64,18 +220,219 @@ pub(crate) fn get_name (device: &str) -> Result<String> {
 	bail!(error)
 }
 
+/// Gets the device-tree name of the specified `device` from its of_node, ex. "eth2@482d0000" -> "eth2".
+fn get_name_from_of_node (device: &str) -> Result<String> {
+	let node = get_of_node(device)?;
+
+	match node.rsplit('/').next().and_then(|name| name.split('@').next()) {
+		Some(name) if ! name.is_empty() => Ok(String::from(name)),
+		_                               => bail!("can't find the device-tree name of node {node}"),
+	}
+}
+
+/// Gets the model of the board, as found in the root node of the device-tree.
+pub(crate) fn get_model () -> Result<String> {
+	let path  = crate::capture::path("/sys/firmware/devicetree/base/model");
+	let model = std::fs::read_to_string(&path).map_err(|error| anyhow!("can't read {}: {error}", path.display()))?;
+
+	Ok(String::from(model.trim_end_matches('\0')))
+}
+
+/// Gets the `gpio` connected to the RGMII GTX clock of the specified `device`.
+///
+/// This function doesn't rely on debugfs: it follows the "pinctrl-0"
+/// phandles of the Ethernet node found from `get_of_node`, decodes
+/// their "pinmux" entries and identifies the GTX clock pin from
+/// `GTX_CLOCK_PINS`.
+pub(crate) fn get_gpio (device: &str) -> Result<Gpio> {
+	let base = crate::capture::path("/sys/firmware/devicetree/base");
+	let node = base.join(get_of_node(device)?.trim_start_matches('/'));
+
+	for phandle in read_cells(node.join("pinctrl-0"))? {
+		for pinctrl in find_pinctrls(&base) {
+			let group = match find_phandle(&pinctrl, phandle) {
+				None        => continue,
+				Some(group) => group,
+			};
+
+			let compatible = std::fs::read_to_string(pinctrl.join("compatible")).unwrap_or_default();
+			let pinmuxes   = find_pinmuxes(&group);
+
+			if ! compatible.split('\0').any(|compatible| GTX_CLOCK_PINS.iter().any(|pin| pin.compatible == compatible)) {
+				bail!("pin controller {} ({}) isn't supported without the pinctrl debugfs: only {} supported",
+				      pinctrl.display(), compatible.trim_end_matches('\0').replace('\0', ", "), describe_gtx_clock_pins());
+			}
+
+			for compatible in compatible.split('\0') {
+				if let Some((bank, line)) = identify_gtx_clock_pin(compatible, &pinmuxes) {
+					return Ok(Gpio {
+						bank,
+						line,
+						pinctrl: pinctrl.file_name().unwrap().to_string_lossy().into(),
+					});
+				}
+			}
+
+			break;
+		}
+	}
+
+	bail!("can't find the GPIO connected to the RGMII GTX clock in device-tree node {}: \
+	       without the pinctrl debugfs, only {} supported", node.display(), describe_gtx_clock_pins())
+}
+
+/// Finds all pin controllers in the device-tree rooted at `base`.
+fn find_pinctrls (base: &Path) -> Vec<PathBuf> {
+	let mut pinctrls = Vec::new();
+
+	for soc in ["soc", "soc@0"] {
+		let read_dir = match std::fs::read_dir(base.join(soc)) {
+			Err(_)       => continue,
+			Ok(read_dir) => read_dir,
+		};
+
+		for entry in read_dir.map_while(Result::ok) {
+			if entry.file_name().to_string_lossy().starts_with("pinctrl@") {
+				pinctrls.push(entry.path());
+			}
+		}
+	}
+
+	pinctrls
+}
+
+/// Finds recursively from `current_dir` the node whose phandle is `phandle`.
+fn find_phandle (current_dir: &Path, phandle: u32) -> Option<PathBuf> {
+	if read_cells(current_dir.join("phandle")).ok().as_deref() == Some(&[phandle]) {
+		return Some(current_dir.to_path_buf());
+	}
+
+	for entry in std::fs::read_dir(current_dir).ok()?.map_while(Result::ok) {
+		if entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false) {
+			if let Some(path) = find_phandle(&entry.path(), phandle) {
+				return Some(path);
+			}
+		}
+	}
+
+	None
+}
+
+/// Finds all "pinmux" entries of the pin group `group`, ie. in its sub-nodes.
+fn find_pinmuxes (group: &Path) -> Vec<u32> {
+	let mut pinmuxes = Vec::new();
+
+	let read_dir = match std::fs::read_dir(group) {
+		Err(error)   => { log::warn!("{error} while opening {}", group.display()); return pinmuxes }
+		Ok(read_dir) => { read_dir }
+	};
+
+	for entry in read_dir.map_while(Result::ok) {
+		match read_cells(entry.path().join("pinmux")) {
+			Ok(mut cells) => pinmuxes.append(&mut cells),
+			Err(error)    => log::debug!("{error}"),
+		}
+	}
+
+	pinmuxes
+}
+
+/// Reads the device-tree property at `path` as a list of big-endian cells.
+fn read_cells<P: AsRef<Path>> (path: P) -> Result<Vec<u32>> {
+	let path    = path.as_ref();
+	let content = std::fs::read(path).map_err(|error| anyhow!("can't read {}: {error}", path.display()))?;
+
+	Ok(content.chunks_exact(4).map(|cell| u32::from_be_bytes([cell[0], cell[1], cell[2], cell[3]])).collect())
+}
+
+/// Identifies the RGMII GTX clock pin among `pinmuxes` for a pin controller `compatible`.
+///
+/// The function names aren't compared: `pinmuxes` only come from the
+/// pin groups of one Ethernet node, whose label doesn't necessarily
+/// match the function name (ex. "ethernet1" in mainline device-trees).
+fn identify_gtx_clock_pin (compatible: &str, pinmuxes: &[u32]) -> Option<(char, u8)> {
+	for pinmux in pinmuxes.iter().map(|value| PinMux::from(*value)) {
+		let found = GTX_CLOCK_PINS.iter().any(|pin| {
+			   pin.compatible == compatible
+			&& pin.bank as u8 - b'A' == pinmux.bank
+			&& pin.line       == pinmux.line
+			&& Some(pin.af)   == pinmux.alternate_function()
+		});
+
+		if found {
+			return Some(((b'A' + pinmux.bank) as char, pinmux.line));
+		}
+	}
+
+	None
+}
+
+#[test]
+fn test_identify_gtx_clock_pin () {
+	let pinmuxes = [0x0000270b, 0x0000570b, 0x0000200b];
+
+	assert_eq!(identify_gtx_clock_pin("st,stm32mp257-pinctrl", &pinmuxes),     Some(('F', 7)));
+	assert_eq!(identify_gtx_clock_pin("st,stm32mp257-pinctrl", &pinmuxes[..1]), None);
+	assert_eq!(identify_gtx_clock_pin("st,stm32mp157-pinctrl", &pinmuxes),     None);
+	assert_eq!(identify_gtx_clock_pin("st,stm32mp257-pinctrl", &[0x00005701]), None);
+}
+
+/// Pin that can carry an RGMII GTX clock on a given SoC.
+struct GtxClockPin {
+	/// Compatible of the pin controller.
+	compatible: &'static str,
+
+	/// Function name, as reported by the pinctrl debugfs.
+	function:   &'static str,
+
+	/// Bank to which the pin belongs, ex. GPIOA.
+	bank:       char,
+
+	/// Line of the pin within the bank.
+	line:       u8,
+
+	/// Alternate function that routes the RGMII GTX clock to this pin.
+	af:         u8,
+}
+
+/// Alternate-function table of all known RGMII GTX clock pins.
+///
+/// Only the pins validated on boards are listed: other ports, variants
+/// and alternate pins (ex. ETH1, stm32mp251/253/255) require the pinctrl
+/// debugfs, see `describe_gtx_clock_pins`.
+const GTX_CLOCK_PINS: &[GtxClockPin] = &[
+	GtxClockPin { compatible: "st,stm32mp257-pinctrl", function: "ETH2_RGMII_GTX_CLK", bank: 'F', line: 7, af: 10 },
+];
+
+/// Describes the pins listed in `GTX_CLOCK_PINS`, ex. "ETH2_RGMII_GTX_CLK on PF7 (AF10) of st,stm32mp257-pinctrl is".
+fn describe_gtx_clock_pins () -> String {
+	let pins = GTX_CLOCK_PINS.iter()
+	           .map(|pin| format!("{} on P{}{} (AF{}) of {}", pin.function, pin.bank, pin.line, pin.af, pin.compatible))
+	           .collect::<Vec<_>>();
+
+	match pins.len() {
+		1 => format!("{} is", pins[0]),
+		_ => format!("{} are", pins.join(", ")),
+	}
+}
+
+#[test]
+fn test_describe_gtx_clock_pins () {
+	assert_eq!(describe_gtx_clock_pins(), "ETH2_RGMII_GTX_CLK on PF7 (AF10) of st,stm32mp257-pinctrl is");
+}
+
 /// Finds all device-tree nodes for the specified `gpio`.
 ///
 /// This function actually parses "/sys/firmware/devicetree/base".
 pub(crate) fn find_nodes(gpio: &Gpio) -> Vec<String> {
 	let mut paths = Vec::new();
 
-	let base = "/sys/firmware/devicetree/base";
+	let base = crate::capture::path("/sys/firmware/devicetree/base");
 
-	find_paths(format!("{base}/soc/{}", gpio.pinctrl), gpio, &mut paths);
-	find_paths(format!("{base}/soc@0/{}", gpio.pinctrl), gpio, &mut paths);
+	find_paths(base.join("soc").join(&gpio.pinctrl), gpio, &mut paths);
+	find_paths(base.join("soc@0").join(&gpio.pinctrl), gpio, &mut paths);
 
-	paths.iter().map(|path| format!("/{}", path.strip_prefix(base).unwrap().display())).collect()
+	paths.iter().map(|path| format!("/{}", path.strip_prefix(&base).unwrap().display())).collect()
 }
 
 /// Finds all paths recursely from `current_dir` that match the specified `gpio`.
@@ -151,14 +508,24 @@ struct PinMux {
 	/// Line of the pin within the bank.
 	line:  u8,
 
-	/// Mode of the pin, currently unused.
-	_mode: u8,
+	/// Mode of the pin: GPIO (0), alternate function 0 to 15 (1 to 16) or analog (17).
+	mode:  u8,
+}
+
+impl PinMux {
+	/// Gets the alternate function of the pin, if any.
+	fn alternate_function (&self) -> Option<u8> {
+		match self.mode {
+			mode @ 1..=16 => Some(mode - 1),
+			_             => None,
+		}
+	}
 }
 
 impl From<u32> for PinMux {
 	fn from(value: u32) -> Self {
 		PinMux {
-			_mode: (value & 0xFF)           as u8,
+			mode:  (value & 0xFF)           as u8,
 			line:  ((value & 0xF00) >> 8)   as u8,
 			bank:  ((value & 0xF000) >> 12) as u8,
 		}
@@ -171,5 +538,6 @@ fn from_u32_for_pinmux () {
 
 	assert_eq!(pinmux.bank,  5);
 	assert_eq!(pinmux.line,  8);
-	assert_eq!(pinmux._mode, 0xb);
+	assert_eq!(pinmux.mode,  0xb);
+	assert_eq!(pinmux.alternate_function(), Some(10));
 }
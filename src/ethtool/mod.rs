@@ -0,0 +1,190 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+mod netlink;
+
+use nix::sys::socket::{socket, AddressFamily, SockType, SockFlag};
+use std::collections::{BTreeMap, HashMap};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use anyhow::Result;
+
+/// Get Network Interface Card (NIC) statistics for the specified `device`.
+///
+/// This includes the driver-private statistics, ex. "mmc_rx_crc_error",
+/// and the standard statistics groups, ex. "eth-mac.FrameCheckSequenceErrors".
+/// The latter are not available on kernels without ethtool netlink support.
+pub(crate) fn get_nic_stats(device: &str) -> Result<HashMap<String, u64>> {
+	log::debug!("getting NIC statistics");
+
+	if let Some(path) = crate::capture::replayed(format!("/ethtool/{device}")) {
+		return crate::capture::read_nic_stats(&path);
+	}
+
+	let mut nic_stats = get_private_stats(device)?;
+
+	match netlink::get_standard_stats(device) {
+		Ok(standard_stats) => nic_stats.extend(standard_stats),
+		Err(error)         => log::debug!("{error}, only driver-private statistics are available"),
+	}
+
+	Ok(nic_stats)
+}
+
+/// Gets how many times the link of the specified `device` went down, ex. when autonegotiation restarted.
+///
+/// This is None if the kernel or the driver doesn't report it.
+pub(crate) fn get_link_down_events(device: &str) -> Result<Option<u32>> {
+	netlink::get_link_down_events(device)
+}
+
+/// Computes the non-zero deltas between the `before` and `after` snapshots of NIC statistics.
+///
+/// Statistics that decreased, ie. reset in between, are ignored.
+pub(crate) fn diff(before: &HashMap<String, u64>, after: &HashMap<String, u64>) -> BTreeMap<String, u64> {
+	after.iter()
+	.filter_map(|(name, after)| {
+		let before = before.get(name).copied().unwrap_or(0);
+		match after.checked_sub(before) {
+			None | Some(0) => None,
+			Some(delta)    => Some((name.clone(), delta)),
+		}
+	})
+	.collect()
+}
+
+#[test]
+fn test_diff () {
+	let before = HashMap::from([(String::from("a"), 1), (String::from("b"), 5), (String::from("c"), 7)]);
+	let after  = HashMap::from([(String::from("a"), 3), (String::from("b"), 5), (String::from("c"), 2), (String::from("d"), 4)]);
+
+	assert_eq!(diff(&before, &after), BTreeMap::from([(String::from("a"), 2), (String::from("d"), 4)]));
+}
+
+/// Get driver-private NIC statistics for the specified `device`, through the legacy ioctl interface.
+fn get_private_stats(device: &str) -> Result<HashMap<String, u64>> {
+	let handle = socket(AddressFamily::Inet, SockType::Datagram, SockFlag::empty(), None)
+	             .map_err(|error| anyhow!("can't create socket: {error}"))?;
+	let handle = unsafe { OwnedFd::from_raw_fd(handle) };
+
+	let mut sset_info = self::sset_info {
+		cmd:       self::GSSET_INFO,
+		reserved:  0,
+		sset_mask: 1 << self::SS_STATS,
+		data:      [0],
+	};
+
+	self::send_ioctl(handle.as_raw_fd(), device, std::ptr::addr_of_mut!(sset_info) as *mut libc::c_void)?;
+
+	let nb_stats = sset_info.data[0] as usize;
+
+	// Buffers are sized dynamically since some NICs expose hundreds of statistics:
+	// - struct ethtool_gstrings { u32 cmd; u32 string_set; u32 len; u8 data[]; }
+	// - struct ethtool_stats { u32 cmd; u32 n_stats; u64 data[]; }
+	let mut names  = vec![0u8;  GSTRINGS_HDRLEN + nb_stats * self::GSTRING_LEN];
+	let mut values = vec![0u64; 1 + nb_stats];
+
+	names[0..4].copy_from_slice(&self::GSTRINGS.to_ne_bytes());
+	names[4..8].copy_from_slice(&self::SS_STATS.to_ne_bytes());
+	names[8..12].copy_from_slice(&(nb_stats as u32).to_ne_bytes());
+
+	values[0] = u64::from_ne_bytes([self::GSTATS.to_ne_bytes(), (nb_stats as u32).to_ne_bytes()].concat().try_into().unwrap());
+
+	self::send_ioctl(handle.as_raw_fd(), device, names.as_mut_ptr()  as *mut libc::c_void)?;
+	self::send_ioctl(handle.as_raw_fd(), device, values.as_mut_ptr() as *mut libc::c_void)?;
+
+	// The kernel might report fewer statistics than initially announced.
+	let nb_names  = u32::from_ne_bytes(names[8..12].try_into().unwrap()) as usize;
+	let nb_values = (values[0].to_ne_bytes()[4..8].try_into().map(u32::from_ne_bytes).unwrap()) as usize;
+
+	Ok(std::iter::zip(names[GSTRINGS_HDRLEN..].chunks(self::GSTRING_LEN), &values[1..])
+	   .take(nb_names.min(nb_values).min(nb_stats))
+	   .map(|(name, value)| (String::from_utf8_lossy(name).trim_end_matches('\0').into(), *value))
+	   .collect())
+}
+
+/// Statistics set information, see ethtool or Linux sources for details.
+#[repr(C)]
+#[derive(Debug)]
+struct sset_info<T: ?Sized> {
+	pub cmd:       u32,
+	pub reserved:  u32,
+	pub sset_mask: u64,
+	pub data:      T,
+}
+
+nix::ioctl_write_ptr_bad!(ioctl_write, libc::SIOCETHTOOL, libc::ifreq);
+
+/// Sends `data` to the specified `device` driver through its ioctl interface.
+fn send_ioctl(handle: libc::c_int, device: &str, data: *mut libc::c_void) -> Result<i32> {
+	let ifr = libc::ifreq {
+		ifr_name: convert_to_ifr_name(device)?,
+		ifr_ifru: libc::__c_anonymous_ifr_ifru {
+			ifru_data: data as *mut libc::c_char,
+		},
+	};
+
+	unsafe {
+		self::ioctl_write(handle, std::ptr::addr_of!(ifr))
+		.map_err(|error| anyhow!("can't perform ioctl write: {error}"))
+	}
+}
+
+/// Converts the interface `name` from a `&str` to a `[libc::c_char; 16]`.
+fn convert_to_ifr_name (name: &str) -> Result<[libc::c_char; 16]> {
+	if name.len() >= 16 {
+		bail!("IFR name \"{name}\" is too long, it should be <= 16 characters");
+	}
+
+	let mut result = [0; 16];
+
+	for (index, char) in name.chars().enumerate() {
+		result[index] = char as libc::c_char;
+	}
+
+	Ok(result)
+}
+
+/// Command to get statistics set information.
+const GSSET_INFO:  u32   = 0x00000037;
+
+/// Statistics set identifier.
+const SS_STATS:    u32   = 1;
+
+/// Maximum length of strings.
+const GSTRING_LEN: usize = 32;
+
+/// Command to get strings.
+const GSTRINGS:    u32   = 0x0000001b;
+
+/// Length of the header of strings, ie. before the actual strings.
+const GSTRINGS_HDRLEN: usize = 12;
+
+/// Command to get statistics.
+const GSTATS:      u32   = 0x0000001d;
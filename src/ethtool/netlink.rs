@@ -0,0 +1,240 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::HashMap;
+
+use crate::netlink::{self, Attributes, Socket};
+use anyhow::Result;
+
+/// Gets the standard statistics of the specified `device` through the ethtool generic netlink family.
+///
+/// Statistics are named after their group, ex. "eth-mac.FrameCheckSequenceErrors".
+pub(crate) fn get_standard_stats (device: &str) -> Result<HashMap<String, u64>> {
+	let mut socket = Socket::open(libc::NETLINK_GENERIC)?;
+	let family     = netlink::resolve_family(&mut socket, "ethtool")?;
+
+	let string_sets = GROUPS.iter().map(|(_, string_set)| *string_set).collect::<Vec<_>>();
+	let names       = get_strings(&mut socket, family, device, &string_sets)?;
+
+	let mut bitset = Attributes::new();
+	bitset.push(ETHTOOL_A_BITSET_NOMASK, &[])
+	      .push_u32(ETHTOOL_A_BITSET_SIZE, GROUPS.len() as u32)
+	      .push_u32(ETHTOOL_A_BITSET_VALUE, (1 << GROUPS.len()) - 1);
+
+	let mut attributes = Attributes::new();
+	attributes.push_nested(ETHTOOL_A_STATS_HEADER, header(device))
+	          .push_nested(ETHTOOL_A_STATS_GROUPS, bitset);
+
+	let replies = socket.request(family, 0, &attributes.into_genl(ETHTOOL_MSG_STATS_GET, ETHTOOL_GENL_VERSION))?;
+
+	let mut stats = HashMap::new();
+
+	for reply in &replies {
+		for (kind, group) in netlink::parse(reply.get(netlink::GENL_HDRLEN..).unwrap_or_default()) {
+			if kind != ETHTOOL_A_STATS_GRP {
+				continue;
+			}
+
+			let mut group_name = None;
+			let mut string_set = None;
+			let mut values     = Vec::new();
+
+			for (kind, value) in netlink::parse(group) {
+				match kind {
+					ETHTOOL_A_STATS_GRP_ID    => group_name = netlink::read_u32(value).and_then(|id| GROUPS.get(id as usize)).map(|(name, _)| *name),
+					ETHTOOL_A_STATS_GRP_SS_ID => string_set = netlink::read_u32(value),
+					ETHTOOL_A_STATS_GRP_STAT  => values.extend(netlink::parse(value).filter_map(|(index, value)| Some((index, netlink::read_u64(value)?)))),
+					_                         => {}
+				}
+			}
+
+			let (group_name, string_set) = match (group_name, string_set) {
+				(Some(group_name), Some(string_set)) => (group_name, string_set),
+				_                                    => { log::warn!("malformed ethtool statistics group"); continue }
+			};
+
+			for (index, value) in values {
+				match names.get(&string_set).and_then(|names| names.get(index as usize)) {
+					Some(name) => { stats.insert(format!("{group_name}.{name}"), value); }
+					None       => { log::warn!("unknown statistic #{index} in ethtool group {group_name}") }
+				}
+			}
+		}
+	}
+
+	Ok(stats)
+}
+
+/// Gets the number of link down events of the specified `device` through the ethtool generic netlink family.
+///
+/// This is None if the kernel (before Linux 6.4) or the driver doesn't
+/// report `ETHTOOL_A_LINKSTATE_EXT_DOWN_CNT`.
+pub(crate) fn get_link_down_events (device: &str) -> Result<Option<u32>> {
+	let mut socket = Socket::open(libc::NETLINK_GENERIC)?;
+	let family     = netlink::resolve_family(&mut socket, "ethtool")?;
+
+	let mut attributes = Attributes::new();
+	attributes.push_nested(ETHTOOL_A_LINKSTATE_HEADER, header(device));
+
+	let replies = socket.request(family, 0, &attributes.into_genl(ETHTOOL_MSG_LINKSTATE_GET, ETHTOOL_GENL_VERSION))?;
+
+	let down_events = replies.iter()
+	                  .flat_map(|reply| netlink::parse(reply.get(netlink::GENL_HDRLEN..).unwrap_or_default()))
+	                  .find(|(kind, _)| *kind == ETHTOOL_A_LINKSTATE_EXT_DOWN_CNT)
+	                  .and_then(|(_, value)| netlink::read_u32(value));
+
+	Ok(down_events)
+}
+
+/// Gets the strings of the specified `string_sets` for `device`, indexed by string set.
+fn get_strings (socket: &mut Socket, family: u16, device: &str, string_sets: &[u32]) -> Result<HashMap<u32, Vec<String>>> {
+	let mut sets = Attributes::new();
+
+	for string_set in string_sets {
+		let mut set = Attributes::new();
+		set.push_u32(ETHTOOL_A_STRINGSET_ID, *string_set);
+		sets.push_nested(ETHTOOL_A_STRINGSETS_STRINGSET, set);
+	}
+
+	let mut attributes = Attributes::new();
+	attributes.push_nested(ETHTOOL_A_STRSET_HEADER, header(device))
+	          .push_nested(ETHTOOL_A_STRSET_STRINGSETS, sets);
+
+	let replies = socket.request(family, 0, &attributes.into_genl(ETHTOOL_MSG_STRSET_GET, ETHTOOL_GENL_VERSION))?;
+
+	let mut result = HashMap::new();
+
+	for reply in &replies {
+		let sets = netlink::parse(reply.get(netlink::GENL_HDRLEN..).unwrap_or_default())
+		           .filter(|(kind, _)| *kind == ETHTOOL_A_STRSET_STRINGSETS)
+		           .flat_map(|(_, sets)| netlink::parse(sets))
+		           .filter(|(kind, _)| *kind == ETHTOOL_A_STRINGSETS_STRINGSET);
+
+		for (_, set) in sets {
+			let mut id      = None;
+			let mut strings = Vec::new();
+
+			for (kind, value) in netlink::parse(set) {
+				match kind {
+					ETHTOOL_A_STRINGSET_ID      => id = netlink::read_u32(value),
+					ETHTOOL_A_STRINGSET_STRINGS => strings.extend(netlink::parse(value).map(|(_, string)| parse_string(string))),
+					_                           => {}
+				}
+			}
+
+			if let Some(id) = id {
+				let mut names = Vec::new();
+
+				for (index, name) in strings.into_iter().flatten() {
+					if names.len() <= index as usize {
+						names.resize(index as usize + 1, String::new());
+					}
+					names[index as usize] = name;
+				}
+
+				result.insert(id, names);
+			}
+		}
+	}
+
+	Ok(result)
+}
+
+/// Parses a single string of a string set, ie. its index and its value.
+fn parse_string (data: &[u8]) -> Option<(u32, String)> {
+	let mut index = None;
+	let mut name  = None;
+
+	for (kind, value) in netlink::parse(data) {
+		match kind {
+			ETHTOOL_A_STRING_INDEX => index = netlink::read_u32(value),
+			ETHTOOL_A_STRING_VALUE => name  = Some(netlink::read_string(value)),
+			_                      => {}
+		}
+	}
+
+	Some((index?, name?))
+}
+
+/// Builds the request header for the specified `device`.
+fn header (device: &str) -> Attributes {
+	let mut header = Attributes::new();
+	header.push_string(ETHTOOL_A_HEADER_DEV_NAME, device);
+	header
+}
+
+/// Standard statistics groups, ie. their name and their string set.
+const GROUPS: [(&str, u32); 4] = [
+	("eth-phy",  17),
+	("eth-mac",  18),
+	("eth-ctrl", 19),
+	("rmon",     20),
+];
+
+/// Version of the ethtool generic netlink family.
+const ETHTOOL_GENL_VERSION: u8 = 1;
+
+/// Command to get string sets.
+const ETHTOOL_MSG_STRSET_GET: u8 = 1;
+
+/// Command to get link state.
+const ETHTOOL_MSG_LINKSTATE_GET: u8 = 6;
+
+/// Command to get standard statistics.
+const ETHTOOL_MSG_STATS_GET: u8 = 32;
+
+/// Attribute holding the device name in a request header.
+const ETHTOOL_A_HEADER_DEV_NAME: u16 = 2;
+
+/// Attributes of bitsets, see ethtool-netlink documentation in Linux sources for details.
+const ETHTOOL_A_BITSET_NOMASK: u16 = 1;
+const ETHTOOL_A_BITSET_SIZE:   u16 = 2;
+const ETHTOOL_A_BITSET_VALUE:  u16 = 4;
+
+/// Attributes of string sets, see ethtool-netlink documentation in Linux sources for details.
+const ETHTOOL_A_STRSET_HEADER:        u16 = 1;
+const ETHTOOL_A_STRSET_STRINGSETS:    u16 = 2;
+const ETHTOOL_A_STRINGSETS_STRINGSET: u16 = 1;
+const ETHTOOL_A_STRINGSET_ID:         u16 = 1;
+const ETHTOOL_A_STRINGSET_STRINGS:    u16 = 3;
+const ETHTOOL_A_STRING_INDEX:         u16 = 1;
+const ETHTOOL_A_STRING_VALUE:         u16 = 2;
+
+/// Attributes of link state, see ethtool-netlink documentation in Linux sources for details.
+const ETHTOOL_A_LINKSTATE_HEADER:       u16 = 1;
+const ETHTOOL_A_LINKSTATE_EXT_DOWN_CNT: u16 = 7;
+
+/// Attributes of statistics, see ethtool-netlink documentation in Linux sources for details.
+const ETHTOOL_A_STATS_HEADER:    u16 = 2;
+const ETHTOOL_A_STATS_GROUPS:    u16 = 3;
+const ETHTOOL_A_STATS_GRP:       u16 = 4;
+const ETHTOOL_A_STATS_GRP_ID:    u16 = 2;
+const ETHTOOL_A_STATS_GRP_SS_ID: u16 = 3;
+const ETHTOOL_A_STATS_GRP_STAT:  u16 = 4;
@@ -0,0 +1,190 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::clock_delay;
+use crate::ethtool;
+use anyhow::{Context, Result};
+
+/// Content type of the OpenMetrics text format.
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Maximum time spent reading a request or writing a response, so that a stalled client can't block the other ones.
+const CLIENT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Serves RGMII GTX clock delay and NIC statistics as OpenMetrics text on `listen`, ex. ":9100".
+pub(crate) fn perform (selector: &str, listen: &str) -> Result<()> {
+	let handle = clock_delay::Handle::resolve(selector)?;
+
+	let listen   = if listen.starts_with(':') { format!("0.0.0.0{listen}") } else { listen.to_string() };
+	let listener = TcpListener::bind(&listen).with_context(|| format!("can't listen on {listen}"))?;
+
+	println!("Exporting metrics of device \"{}\" on http://{listen}/metrics", handle.device);
+
+	for stream in listener.incoming() {
+		let result = stream.map_err(anyhow::Error::from)
+		             .and_then(|stream| serve(stream, &handle));
+
+		if let Err(error) = result {
+			log::warn!("can't serve request: {error:#}");
+		}
+	}
+
+	Ok(())
+}
+
+/// Answers a single HTTP request, only "GET /metrics" is supported.
+fn serve (mut stream: TcpStream, handle: &clock_delay::Handle) -> Result<()> {
+	stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+	stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+	let mut request = String::new();
+	let mut reader  = BufReader::new(&stream);
+
+	reader.read_line(&mut request)?;
+
+	// Skip the headers, the request has no body.
+	let mut header = String::new();
+	while reader.read_line(&mut header)? > 2 {
+		header.clear();
+	}
+
+	log::debug!("request: {}", request.trim_end());
+
+	let (status, content_type, body) = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+		["GET", "/metrics"] => match collect(handle) {
+			Ok(body)   => ("200 OK", CONTENT_TYPE, body),
+			Err(error) => ("500 Internal Server Error", "text/plain", format!("{error:#}\n")),
+		},
+		["GET", _] => ("404 Not Found",          "text/plain", "Metrics are available at /metrics\n".to_string()),
+		_          => ("405 Method Not Allowed", "text/plain", "Only GET is supported\n".to_string()),
+	};
+
+	write!(stream, "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+	       body.len())?;
+
+	Ok(())
+}
+
+/// Reads the current RGMII GTX clock delay and NIC statistics, then renders them.
+fn collect (handle: &clock_delay::Handle) -> Result<String> {
+	let bits  = handle.value.get()?;
+	let stats = ethtool::get_nic_stats(&handle.device)?;
+
+	Ok(render(handle, bits, &stats))
+}
+
+/// Renders metrics as OpenMetrics text.
+fn render (handle: &clock_delay::Handle, bits: u32, stats: &HashMap<String, u64>) -> String {
+	let labels = format!("device=\"{}\",dt_name=\"{}\",gpio_bank=\"{}\",gpio_line=\"{}\",pinctrl=\"{}\"",
+	                     escape(&handle.device), escape(&handle.dt_name),
+	                     handle.gpio.bank, handle.gpio.line, escape(&handle.gpio.pinctrl));
+
+	let mut output = String::new();
+
+	// Writing into a String can't fail.
+	let _ = writeln!(output, "# TYPE rgmii_gtx_clock_delay info");
+	let _ = writeln!(output, "# HELP rgmii_gtx_clock_delay Device-tree name and GPIO of the RGMII GTX clock.");
+	let _ = writeln!(output, "rgmii_gtx_clock_delay_info{{{labels}}} 1");
+
+	let _ = writeln!(output, "# TYPE rgmii_gtx_clock_delay_bits gauge");
+	let _ = writeln!(output, "# HELP rgmii_gtx_clock_delay_bits Raw value of the RGMII GTX clock delay register field.");
+	let _ = writeln!(output, "rgmii_gtx_clock_delay_bits{{device=\"{}\"}} {bits}", escape(&handle.device));
+
+	let _ = writeln!(output, "# TYPE rgmii_gtx_clock_delay_nanoseconds gauge");
+	let _ = writeln!(output, "# HELP rgmii_gtx_clock_delay_nanoseconds RGMII GTX clock delay.");
+	match clock_delay::convert_to_ns(bits) {
+		Ok(ns)     => { let _ = writeln!(output, "rgmii_gtx_clock_delay_nanoseconds{{device=\"{}\"}} {}",
+		                                 escape(&handle.device), clock_delay::convert_to_f64(ns)); },
+		Err(error) => log::warn!("{error}"),
+	}
+
+	let _ = writeln!(output, "# TYPE nic_statistic unknown");
+	let _ = writeln!(output, "# HELP nic_statistic NIC statistic as reported by ethtool.");
+
+	let mut names = stats.keys().collect::<Vec<_>>();
+	names.sort();
+
+	for name in names {
+		let _ = writeln!(output, "nic_statistic{{device=\"{}\",name=\"{}\"}} {}",
+		                 escape(&handle.device), escape(name), stats[name]);
+	}
+
+	output.push_str("# EOF\n");
+	output
+}
+
+/// Escapes a label value as required by OpenMetrics.
+fn escape (value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[test]
+fn test_render () {
+	use clock_delay::register::Mock;
+
+	let handle = clock_delay::Handle {
+		device:  "eth1".to_string(),
+		dt_name: "ethernet@482d0000".to_string(),
+		gpio:    clock_delay::Gpio { bank: 'F', line: 7, pinctrl: "soc@0/pinctrl@44240000".to_string() },
+		address: clock_delay::Address { base: 0x4424_5040, offset: 28 },
+		value:   clock_delay::Value::new(Box::new(Mock::new(0)), 28),
+	};
+
+	let stats = HashMap::from([
+		("mmc_rx_crc_error".to_string(), 3),
+		("rx_pkt_n".to_string(),         1000),
+	]);
+
+	assert_eq!(render(&handle, 1, &stats), "\
+# TYPE rgmii_gtx_clock_delay info
+# HELP rgmii_gtx_clock_delay Device-tree name and GPIO of the RGMII GTX clock.
+rgmii_gtx_clock_delay_info{device=\"eth1\",dt_name=\"ethernet@482d0000\",gpio_bank=\"F\",gpio_line=\"7\",pinctrl=\"soc@0/pinctrl@44240000\"} 1
+# TYPE rgmii_gtx_clock_delay_bits gauge
+# HELP rgmii_gtx_clock_delay_bits Raw value of the RGMII GTX clock delay register field.
+rgmii_gtx_clock_delay_bits{device=\"eth1\"} 1
+# TYPE rgmii_gtx_clock_delay_nanoseconds gauge
+# HELP rgmii_gtx_clock_delay_nanoseconds RGMII GTX clock delay.
+rgmii_gtx_clock_delay_nanoseconds{device=\"eth1\"} 0.3
+# TYPE nic_statistic unknown
+# HELP nic_statistic NIC statistic as reported by ethtool.
+nic_statistic{device=\"eth1\",name=\"mmc_rx_crc_error\"} 3
+nic_statistic{device=\"eth1\",name=\"rx_pkt_n\"} 1000
+# EOF
+");
+
+	assert!(render(&handle, 15, &stats).contains("rgmii_gtx_clock_delay_nanoseconds{device=\"eth1\"} 3.25\n"));
+
+	assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+}
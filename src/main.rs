@@ -33,6 +33,14 @@
 mod clock_delay;
 mod benchmark;
 mod device_tree;
+mod capture;
+mod netlink;
+mod rtnetlink;
+mod ethtool;
+mod stats;
+mod watch;
+mod monitor;
+mod exporter;
 
 use clap::{Parser, Subcommand};
 use byte_unit::Byte;
@@ -54,22 +62,106 @@ fn main () -> Result<()> {
 	        .color(stderrlog::ColorChoice::Never)
 	        .init();
 
+	let _replay = match &options.from_capture {
+		None          => None,
+		Some(archive) => Some(capture::replay(archive).context("can't replay capture")?),
+	};
+
 	match options.command {
-		Command::Benchmark {device, url, speed_low_limit, timeout } => {
-			benchmark::perform(&device, &url, speed_low_limit, timeout)
+		Command::Benchmark {device, url, speed_low_limit, timeout, counter, stats_diff, stats_report, save, pcb_revision, passes, repeat_per_step, order, seed, strategy, hysteresis, eye, adaptive, probe_bytes, bytes_per_step, seconds_per_step, confidence, target_rate, max_bytes_per_step, retries, keep_alive, settle } => {
+			let settings = benchmark::Settings {
+				url,
+				speed_low_limit,
+				timeout,
+				counters: counter,
+				stats_diff,
+				stats_report,
+				save,
+				revision: pcb_revision,
+				passes,
+				repeat: repeat_per_step,
+				order,
+				seed,
+				strategy,
+				hysteresis,
+				eye,
+				adaptive,
+				probe_bytes,
+				bytes_per_step,
+				seconds_per_step,
+				confidence,
+				target_rate,
+				max_bytes_per_step,
+				retries,
+				keep_alive,
+				settle,
+				format: options.format,
+			};
+
+			benchmark::perform(&device, &settings)
 			.context("can't benchmark all possible RGMII GTX clock delays")?
 		}
 
+		Command::Analyze { results, strategy, hysteresis, eye, confidence } => {
+			benchmark::analyze(&results, strategy, hysteresis, eye, confidence, options.format)
+			.with_context(|| format!("can't analyze {}", results.display()))?
+		}
+
+		Command::Aggregate { results, strategy, hysteresis, threshold } => {
+			benchmark::aggregate::perform(&results, strategy, hysteresis, threshold, options.format)
+			.context("can't aggregate benchmark results")?
+		}
+
 		Command::Set { device, clock_delay } => {
-			clock_delay::access(&device, Some(clock_delay), true)
+			clock_delay::access(&device, Some(clock_delay), options.format)
 			.context("can't set RGMII GTX clock delay")?
 		}
 
 		Command::Get { device } => {
-			clock_delay::access(&device, None, true)
+			clock_delay::access(&device, None, options.format)
 			.context("can't get RGMII GTX clock delay")?
 		}
 
+		Command::Watch { device, interval, threshold, counter } => {
+			watch::perform(&device, interval, threshold, counter)
+			.context("can't watch RGMII GTX clock delay")?
+		}
+
+		Command::Monitor { device, interval, threshold, sustain, min_packets, dwell, window, counter } => {
+			let settings = monitor::Settings {
+				interval,
+				threshold,
+				sustain,
+				min_packets,
+				dwell,
+				window,
+				counters: counter,
+			};
+
+			monitor::perform(&device, &settings)
+			.context("can't monitor RGMII GTX clock delay")?
+		}
+
+		Command::Exporter { device, listen } => {
+			exporter::perform(&device, &listen)
+			.context("can't export metrics")?
+		}
+
+		Command::Stats { device, watch, select, save, diff } => {
+			match (device, watch, diff.as_deref()) {
+				(_, _, Some([before, after])) => stats::diff(before, after, &select),
+				(Some(device), Some(watch), _) => stats::watch(&device, &select, watch),
+				(Some(device), None, _)        => stats::print(&device, &select, save.as_deref()),
+				_                              => unreachable!(),
+			}
+			.context("can't handle NIC statistics")?
+		}
+
+		Command::Capture { device, output } => {
+			capture::create(&device, &output)
+			.context("can't capture the environment of the device")?
+		}
+
 		Command::License { } => {
 			println!("\n\
 				Copyright 2023 STMicroelectronics\n\
@@ -115,18 +207,39 @@ struct Options {
 	#[clap(short, long, action = clap::ArgAction::Count)]
 	verbose: u8,
 
+	/// Read everything from this capture instead of from the running system
+	#[clap(long, global = true)]
+	from_capture: Option<std::path::PathBuf>,
+
+	/// Output format of the get, set and benchmark commands
+	#[clap(long, global = true, value_enum, default_value_t = Format::Text)]
+	format: Format,
+
 	#[clap(subcommand)]
 	/// Requested command
 	command: Command,
 }
 
+/// Output formats.
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub(crate) enum Format {
+	/// Human-readable text
+	Text,
+
+	/// JSON, or JSON lines for progress events
+	Json,
+}
+
 /// All available commands
+///
+/// Commands are parsed only once, so the size of the benchmark options doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 #[clap(author, version, about = "Handle STM32MP25 RGMII GTX clock delay")]
 enum Command {
 	/// Benchmark all possible RGMII GTX clock delays
 	Benchmark {
-		/// Device name
+		/// Device name, MAC address, device-tree path or device-tree alias
 		#[clap(short, long)]
 		device: String,
 
@@ -135,17 +248,146 @@ enum Command {
 		url: String,
 
 		/// Skip if transfer rate is below SPEED_LOW_LIMIT/second during more than TIMEOUT seconds
-		#[clap(short, long, default_value = "100 kiB", value_parser = speed_low_limit_parser)]
+		#[clap(short, long, default_value = "100 kiB", value_parser = byte_parser)]
 		speed_low_limit: Byte,
 
 		/// Timemout for SPEED_LOW_LIMIT and for the connection phase.
 		#[clap(short, long, default_value = "5")]
 		timeout: u64,
+
+		/// Counters of CRC errors and received packets: "stmmac", "eth-mac", "rtnetlink" or "ERRORS,PACKETS" [default: auto-detected]
+		#[clap(short, long, value_parser = benchmark::counters::parser)]
+		counter: Option<benchmark::counters::Counters>,
+
+		/// Print the non-zero deltas of all NIC statistics at each step
+		#[clap(long)]
+		stats_diff: bool,
+
+		/// Save the non-zero deltas of all NIC statistics at each step into this file, as JSON lines
+		#[clap(long)]
+		stats_report: Option<std::path::PathBuf>,
+
+		/// Save all raw measurements into this file, as JSON for later analysis, or as CSV if its extension is ".csv"
+		#[clap(long)]
+		save: Option<std::path::PathBuf>,
+
+		/// Revision of the PCB, saved along with the raw measurements for later aggregation
+		#[clap(long, requires = "save")]
+		pcb_revision: Option<String>,
+
+		/// Number of passes over all RGMII GTX clock delays
+		#[clap(long, default_value = "2", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+		passes: usize,
+
+		/// Number of measurements of each RGMII GTX clock delay within a pass
+		#[clap(long, default_value = "1", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+		repeat_per_step: usize,
+
+		/// Order of the RGMII GTX clock delays within each pass
+		#[clap(long, value_enum, default_value_t)]
+		order: benchmark::sweep::Order,
+
+		/// Seed of the random order, for reproducibility [default: generated]
+		#[clap(long)]
+		seed: Option<u64>,
+
+		/// Strategy to select the best RGMII GTX clock delay
+		#[clap(long, value_enum, default_value_t)]
+		strategy: benchmark::strategy::Strategy,
+
+		/// How RGMII GTX clock delays with inconsistent outcomes between passes are handled
+		#[clap(long, value_enum, default_value_t)]
+		hysteresis: benchmark::hysteresis::Policy,
+
+		/// Print the eye-window report: edges, width and margins in ns, and CRC error rate chart
+		#[clap(long)]
+		eye: bool,
+
+		/// Probe one RGMII GTX clock delay out of three, refine around the passing ones, then measure fully around the candidate only; passing windows of 1 or 2 clock delays between two failing probes are missed
+		#[clap(long, conflicts_with_all = ["passes", "order", "seed"])]
+		adaptive: bool,
+
+		/// Size of the short transfers of the adaptive sweep
+		#[clap(long, default_value = "10 MB", value_parser = byte_parser, requires = "adaptive")]
+		probe_bytes: Byte,
+
+		/// Stop the download of each step after this size [default: whole payload]
+		#[clap(long, value_parser = byte_parser)]
+		bytes_per_step: Option<Byte>,
+
+		/// Stop the download of each step after this duration, ex. "10s" [default: unlimited]
+		#[clap(long, value_parser = duration_parser)]
+		seconds_per_step: Option<std::time::Duration>,
+
+		/// Confidence level of the CRC error rate intervals and of the BER upper bounds
+		#[clap(long, default_value = "0.95", value_parser = confidence_parser)]
+		confidence: f64,
+
+		/// Keep downloading at each step until the CRC error rate is known to be below or above this target, in percent, ex. "1e-4"
+		#[clap(long, value_parser = rate_parser)]
+		target_rate: Option<f64>,
+
+		/// Maximum size downloaded at each step when a target CRC error rate is specified
+		#[clap(long, default_value = "1 GB", value_parser = byte_parser, requires = "target_rate")]
+		max_bytes_per_step: Byte,
+
+		/// Number of retries of the steps failing because of DNS, connection or TLS errors
+		#[clap(long, default_value = "2")]
+		retries: usize,
+
+		/// Reuse the same connection across steps, if the server allows it
+		#[clap(long)]
+		keep_alive: bool,
+
+		/// Time waited after each change of RGMII GTX clock delay, once the link runs again, ex. "500ms"
+		#[clap(long, default_value = "500ms", value_parser = duration_parser)]
+		settle: std::time::Duration,
+	},
+
+	/// Recompute the best RGMII GTX clock delay from saved benchmark results
+	Analyze {
+		/// Benchmark results saved with "benchmark --save"
+		results: std::path::PathBuf,
+
+		/// Strategy to select the best RGMII GTX clock delay
+		#[clap(long, value_enum, default_value_t)]
+		strategy: benchmark::strategy::Strategy,
+
+		/// How RGMII GTX clock delays with inconsistent outcomes between passes are handled
+		#[clap(long, value_enum, default_value_t)]
+		hysteresis: benchmark::hysteresis::Policy,
+
+		/// Print the eye-window report: edges, width and margins in ns, and CRC error rate chart
+		#[clap(long)]
+		eye: bool,
+
+		/// Confidence level of the BER upper bounds
+		#[clap(long, default_value = "0.95", value_parser = confidence_parser)]
+		confidence: f64,
+	},
+
+	/// Aggregate saved benchmark results of many boards and recommend one RGMII GTX clock delay per lot
+	Aggregate {
+		/// Benchmark results saved with "benchmark --save"
+		#[clap(required = true)]
+		results: Vec<std::path::PathBuf>,
+
+		/// Strategy to select the best RGMII GTX clock delay of each board
+		#[clap(long, value_enum, default_value_t)]
+		strategy: benchmark::strategy::Strategy,
+
+		/// How RGMII GTX clock delays with inconsistent outcomes between passes are handled
+		#[clap(long, value_enum, default_value_t)]
+		hysteresis: benchmark::hysteresis::Policy,
+
+		/// Consider a RGMII GTX clock delay as failing on a board when its CRC error rate is above this threshold (in percent) [default: none]
+		#[clap(short, long)]
+		threshold: Option<f32>,
 	},
 
 	/// Set RGMII GTX clock delay
 	Set {
-		/// Device name
+		/// Device name, MAC address, device-tree path or device-tree alias
 		#[clap(short, long)]
 		device: String,
 
@@ -156,16 +398,186 @@ enum Command {
 
 	/// Get RGMII GTX clock delay
 	Get {
-		/// Device name
+		/// Device name, MAC address, device-tree path or device-tree alias
 		#[clap(short, long)]
 		device: String,
 	},
 
+	/// Continuously display RGMII GTX clock delay, CRC error rate and link state
+	Watch {
+		/// Device name, MAC address, device-tree path or device-tree alias
+		#[clap(short, long)]
+		device: String,
+
+		/// Refresh interval, ex. "1s" or "500ms"
+		#[clap(short, long, default_value = "1s", value_parser = duration_parser)]
+		interval: std::time::Duration,
+
+		/// Highlight CRC error rates above this threshold (in percent)
+		#[clap(short, long, default_value = "0.1")]
+		threshold: f32,
+
+		/// Counters of CRC errors and received packets: "stmmac", "eth-mac", "rtnetlink" or "ERRORS,PACKETS" [default: auto-detected]
+		#[clap(short, long, value_parser = benchmark::counters::parser)]
+		counter: Option<benchmark::counters::Counters>,
+	},
+
+	/// Monitor CRC error rate and re-tune RGMII GTX clock delay when it degrades
+	Monitor {
+		/// Device name, MAC address, device-tree path or device-tree alias
+		#[clap(short, long)]
+		device: String,
+
+		/// Interval between two samples of the CRC error rate, ex. "10s"
+		#[clap(short, long, default_value = "10s", value_parser = duration_parser)]
+		interval: std::time::Duration,
+
+		/// Re-tune when CRC error rate is above this threshold (in percent)...
+		#[clap(short, long, default_value = "0.1")]
+		threshold: f32,
+
+		/// ... during at least this duration, ex. "5m"
+		#[clap(short, long, default_value = "5m", value_parser = duration_parser)]
+		sustain: std::time::Duration,
+
+		/// Ignore samples with fewer received packets than this
+		#[clap(short, long, default_value = "1000")]
+		min_packets: u64,
+
+		/// Duration during which each candidate RGMII GTX clock delay is evaluated, ex. "1m"
+		#[clap(long, default_value = "1m", value_parser = duration_parser)]
+		dwell: std::time::Duration,
+
+		/// Only re-tune within this daily maintenance window (local time), ex. "02:00-04:00"
+		#[clap(short, long, value_parser = monitor::window_parser)]
+		window: Option<monitor::Window>,
+
+		/// Counters of CRC errors and received packets: "stmmac", "eth-mac", "rtnetlink" or "ERRORS,PACKETS" [default: auto-detected]
+		#[clap(short, long, value_parser = benchmark::counters::parser)]
+		counter: Option<benchmark::counters::Counters>,
+	},
+
+	/// Serve RGMII GTX clock delay and NIC statistics as OpenMetrics text
+	Exporter {
+		/// Device name, MAC address, device-tree path or device-tree alias
+		#[clap(short, long)]
+		device: String,
+
+		/// Address to listen on, ex. ":9100" or "127.0.0.1:9100"
+		#[clap(short, long, default_value = ":9100")]
+		listen: String,
+	},
+
+	/// Print, watch or compare NIC statistics
+	Stats {
+		/// Device name, MAC address, device-tree path or device-tree alias
+		#[clap(short, long, required_unless_present = "diff")]
+		device: Option<String>,
+
+		/// Print the deltas of NIC statistics at this interval, ex. "1s" or "500ms"
+		#[clap(short, long, value_parser = duration_parser, conflicts_with = "save")]
+		watch: Option<std::time::Duration>,
+
+		/// Only handle these NIC statistics (comma separated)
+		#[clap(short, long, value_delimiter = ',')]
+		select: Vec<String>,
+
+		/// Save a snapshot of NIC statistics into this file, as JSON
+		#[clap(long)]
+		save: Option<std::path::PathBuf>,
+
+		/// Compare two snapshots of NIC statistics previously saved with --save
+		#[clap(long, num_args = 2, value_names = ["BEFORE", "AFTER"], conflicts_with_all = ["device", "watch", "save"])]
+		diff: Option<Vec<std::path::PathBuf>>,
+	},
+
+	/// Archive everything read from the system into a tarball for offline replay
+	Capture {
+		/// Device name, MAC address, device-tree path or device-tree alias
+		#[clap(short, long)]
+		device: String,
+
+		/// Tarball where the capture is saved
+		#[clap(short, long)]
+		output: std::path::PathBuf,
+	},
+
 	/// Print license & copyright for this software
 	License { }
 }
 
-/// Parses a `&str` into a `Byte` for the `--speed-low-limit` option.
-fn speed_low_limit_parser (value: &str) -> Result<Byte> {
+/// Parses a `&str` into a `Duration`, ex. "1s", "500ms" or "2" (in seconds).
+fn duration_parser (value: &str) -> Result<std::time::Duration> {
+	// Only the trailing letters are the unit, ex. "1.5e-1ms".
+	let value          = value.trim();
+	let (number, unit) = value.split_at(value.trim_end_matches(|char: char| char.is_alphabetic()).len());
+	let unit           = if unit.is_empty() { "s" } else { unit };
+
+	let number = number.trim().parse::<f64>().map_err(|error| anyhow!("not a valid duration ({error})"))?;
+
+	let seconds = match unit {
+		"ms" => number / 1000.0,
+		"s"  => number,
+		"m"  => number * 60.0,
+		"h"  => number * 3600.0,
+		_    => bail!("not a valid duration unit, must be one of \"ms\", \"s\", \"m\" or \"h\""),
+	};
+
+	std::time::Duration::try_from_secs_f64(seconds).map_err(|error| anyhow!("not a valid duration ({error})"))
+}
+
+#[test]
+fn test_duration_parser () {
+	use std::time::Duration;
+
+	assert_eq!(duration_parser("10").unwrap(),       Duration::from_secs(10));
+	assert_eq!(duration_parser("10s").unwrap(),      Duration::from_secs(10));
+	assert_eq!(duration_parser("250ms").unwrap(),    Duration::from_millis(250));
+	assert_eq!(duration_parser("2m").unwrap(),       Duration::from_secs(120));
+	assert_eq!(duration_parser("1.5h").unwrap(),     Duration::from_secs(5400));
+	assert_eq!(duration_parser("1e3").unwrap(),      Duration::from_secs(1000));
+	assert_eq!(duration_parser("1e3s").unwrap(),     Duration::from_secs(1000));
+	assert_eq!(duration_parser("1.5e-1ms").unwrap(), Duration::from_micros(150));
+	assert_eq!(duration_parser(" 3 s ").unwrap(),    Duration::from_secs(3));
+	assert!(duration_parser("10d").is_err());
+	assert!(duration_parser("ms").is_err());
+	assert!(duration_parser("-1s").is_err());
+}
+
+/// Parses a `&str` into a confidence level for the `--confidence` option.
+fn confidence_parser (value: &str) -> Result<f64> {
+	let level = value.parse::<f64>().map_err(|error| anyhow!("not a valid confidence level ({error})"))?;
+
+	if ! (0.0 < level && level < 1.0) {
+		bail!("not a valid confidence level, must be between 0 and 1 excluded");
+	}
+
+	Ok(level)
+}
+
+/// Parses a `&str` into a CRC error rate in percent for the `--target-rate` option.
+fn rate_parser (value: &str) -> Result<f64> {
+	let rate = value.parse::<f64>().map_err(|error| anyhow!("not a valid CRC error rate ({error})"))?;
+
+	if ! (0.0 < rate && rate <= 100.0) {
+		bail!("not a valid CRC error rate, must be a percentage above 0 and at most 100");
+	}
+
+	Ok(rate)
+}
+
+#[test]
+fn test_rate_parser () {
+	assert_eq!(rate_parser("1e-4").unwrap(), 1e-4);
+	assert_eq!(rate_parser("100").unwrap(),  100.0);
+	assert!(rate_parser("0").is_err());
+	assert!(rate_parser("-1").is_err());
+	assert!(rate_parser("NaN").is_err());
+	assert!(rate_parser("100.1").is_err());
+	assert!(rate_parser("1%").is_err());
+}
+
+/// Parses a `&str` into a `Byte` for the `--speed-low-limit`, `--probe-bytes`, `--bytes-per-step` and `--max-bytes-per-step` options.
+fn byte_parser (value: &str) -> Result<Byte> {
 	Byte::from_str(value).map_err(|error| anyhow!("not a valid size in bytes ({error})"))
 }
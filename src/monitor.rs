@@ -0,0 +1,292 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::time::{Duration, Instant};
+
+use crate::benchmark::counters::Counters;
+use crate::clock_delay;
+use anyhow::Result;
+
+/// Settings of the monitoring.
+pub(crate) struct Settings {
+	/// Interval between two samples of the CRC error rate.
+	pub interval: Duration,
+
+	/// CRC error rate (in percent) above which the link is considered degraded.
+	pub threshold: f32,
+
+	/// Duration during which the CRC error rate must stay above `threshold` before re-tuning.
+	pub sustain: Duration,
+
+	/// Samples with fewer received packets than this are ignored.
+	pub min_packets: u64,
+
+	/// Duration during which each candidate clock delay is evaluated.
+	pub dwell: Duration,
+
+	/// Maintenance window, re-tuning is allowed at any time if not specified.
+	pub window: Option<Window>,
+
+	/// Counters of CRC errors and received packets, auto-detected if not specified.
+	pub counters: Option<Counters>,
+}
+
+/// Monitors the CRC error rate of passive traffic and re-tunes the clock delay when it degrades.
+///
+/// When the CRC error rate stays above the threshold for a sustained
+/// period, only the clock delays adjacent to the current one in
+/// `VALID_VALUES` are evaluated, and only during the maintenance window.
+pub(crate) fn perform (selector: &str, settings: &Settings) -> Result<()> {
+	let mut handle = clock_delay::Handle::resolve(selector)?;
+	handle.ensure_writable()?;
+
+	let counters = match &settings.counters {
+		Some(counters) => counters.clone(),
+		None           => Counters::detect(&handle.device)?,
+	};
+
+	log_event(&format!("Monitoring device \"{}\" using counters {counters}, RGMII GTX clock delay is {:.2} ns",
+	                   handle.device, handle.value.get_as_ns()?));
+
+	let mut previous = counters.read(&handle.device)?;
+	let mut degraded = None;
+
+	loop {
+		std::thread::sleep(settings.interval);
+
+		let current = counters.read(&handle.device)?;
+		let packets = current.1.saturating_sub(previous.1);
+		let percent = (100 * current.0.saturating_sub(previous.0)) as f32 / packets as f32;
+
+		previous = current;
+
+		if packets < settings.min_packets {
+			log::debug!("only {packets} packets received, sample ignored");
+			continue;
+		}
+
+		if percent <= settings.threshold {
+			if degraded.take().is_some() {
+				log_event(&format!("CRC error rate is back to {percent:.2}%"));
+			}
+			continue;
+		}
+
+		let since = *degraded.get_or_insert_with(|| {
+			log_event(&format!("CRC error rate is {percent:.2}%, above {:.2}%", settings.threshold));
+			Instant::now()
+		});
+
+		if since.elapsed() < settings.sustain {
+			continue;
+		}
+
+		if let Some(window) = &settings.window {
+			if ! window.contains(local_minutes()) {
+				log::debug!("CRC error rate is degraded, but outside of maintenance window {window}");
+				continue;
+			}
+		}
+
+		retune(&mut handle, &counters, settings)?;
+
+		degraded = None;
+		previous = counters.read(&handle.device)?;
+	}
+}
+
+/// Evaluates the clock delays adjacent to the current one, then keeps the one with the lowest CRC error rate.
+fn retune (handle: &mut clock_delay::Handle, counters: &Counters, settings: &Settings) -> Result<()> {
+	let values  = &*clock_delay::VALID_VALUES;
+	let current = handle.value.get_as_ns()?;
+	let index   = values.iter().position(|value| *value == current)
+	              .ok_or(anyhow!("current RGMII GTX clock delay {current} ns is not a valid value"))?;
+
+	let candidates = values[index.saturating_sub(1) ..= (index + 1).min(values.len() - 1)].to_vec();
+
+	log_event(&format!("Re-tuning RGMII GTX clock delay among {candidates:?} ns"));
+
+	let mut best = (current, f32::INFINITY);
+
+	for candidate in candidates {
+		// The link must not be left at an untested clock delay.
+		let percent = evaluate(handle, counters, settings, candidate).or_else(|error| {
+			handle.set(current).map_err(|error2| anyhow!("{error}, and can't restore RGMII GTX clock delay {current:.2} ns: {error2}"))?;
+			Err(error)
+		})?;
+
+		if percent < best.1 || (percent == best.1 && candidate == current) {
+			best = (candidate, percent);
+		}
+	}
+
+	handle.set(best.0)?;
+
+	if best.0 == current {
+		log_event(&format!("RGMII GTX clock delay kept at {current:.2} ns"));
+	} else {
+		log_event(&format!("RGMII GTX clock delay changed from {current:.2} ns to {:.2} ns", best.0));
+	}
+
+	Ok(())
+}
+
+/// Sets the clock delay to `candidate`, then measures its CRC error rate during the dwell time.
+fn evaluate (handle: &mut clock_delay::Handle, counters: &Counters, settings: &Settings, candidate: f32) -> Result<f32> {
+	handle.set(candidate)?;
+
+	let before = counters.read(&handle.device)?;
+	std::thread::sleep(settings.dwell);
+	let after  = counters.read(&handle.device)?;
+
+	let packets = after.1.saturating_sub(before.1);
+	let percent = (100 * after.0.saturating_sub(before.0)) as f32 / packets as f32;
+
+	// A delay that breaks the link entirely is the worst candidate.
+	let percent = if packets < settings.min_packets { f32::INFINITY } else { percent };
+
+	log_event(&format!("Candidate RGMII GTX clock delay {candidate:.2} ns: CRC error rate {percent:.2}% ({packets} packets)"));
+
+	Ok(percent)
+}
+
+#[test]
+fn test_retune () {
+	use clock_delay::register::Mock;
+
+	let settings = Settings {
+		interval:    Duration::ZERO,
+		threshold:   1.0,
+		sustain:     Duration::ZERO,
+		min_packets: 1,
+		dwell:       Duration::ZERO,
+		window:      None,
+		counters:    None,
+	};
+
+	let mut handle = clock_delay::Handle {
+		device:  "eth1".to_string(),
+		dt_name: "ethernet@482d0000".to_string(),
+		gpio:    clock_delay::Gpio { bank: 'F', line: 7, pinctrl: "soc@0/pinctrl@44240000".to_string() },
+		address: clock_delay::Address { base: 0x4424_5040, offset: 28 },
+		value:   clock_delay::Value::new(Box::new(Mock::new(0x3000_0000)), 28),
+	};
+
+	// NIC statistics can't be read from this empty root, so the first candidate fails.
+	let root = std::env::temp_dir().join(format!("stm32mp25-rgmii-gtx-clock-delay-retune-{}", std::process::id()));
+	let counters = Counters::Ethtool { errors: "mmc_rx_crc_error".to_string(), packets: "rx_pkt_n".to_string() };
+
+	crate::capture::redirect(Some(root));
+	let result = retune(&mut handle, &counters, &settings);
+	crate::capture::redirect(None);
+
+	assert!(result.is_err());
+	assert_eq!(handle.value.get_as_ns().unwrap(), 0.75);
+}
+
+/// Prints `message` prefixed with the current local time.
+fn log_event (message: &str) {
+	let minutes = local_minutes();
+	println!("[{:02}:{:02}] {message}", minutes / 60, minutes % 60);
+}
+
+/// Gets the current local time, in minutes since midnight.
+fn local_minutes () -> u16 {
+	let now = unsafe { libc::time(std::ptr::null_mut()) };
+	let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+	unsafe { libc::localtime_r(&now, &mut tm) };
+
+	(tm.tm_hour * 60 + tm.tm_min) as u16
+}
+
+/// Daily maintenance window, ex. "02:00-04:00".
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Window {
+	/// Start of the window, in minutes since midnight.
+	start: u16,
+
+	/// End of the window, in minutes since midnight.
+	end:   u16,
+}
+
+impl Window {
+	/// Checks whether `minutes` since midnight is within this window, which might span midnight.
+	fn contains (&self, minutes: u16) -> bool {
+		if self.start <= self.end {
+			self.start <= minutes && minutes < self.end
+		} else {
+			self.start <= minutes || minutes < self.end
+		}
+	}
+}
+
+impl std::fmt::Display for Window {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		write!(formatter, "{:02}:{:02}-{:02}:{:02}", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+	}
+}
+
+/// Parses a `&str` into a `Window` for the `--window` option, ex. "22:30-01:00".
+pub(crate) fn window_parser (value: &str) -> Result<Window> {
+	let parse = |time: &str| -> Result<u16> {
+		let (hours, minutes) = time.split_once(':').ok_or(anyhow!("\"{time}\" is not formatted as HH:MM"))?;
+		let hours   = hours.parse::<u16>().ok().filter(|hours| *hours < 24);
+		let minutes = minutes.parse::<u16>().ok().filter(|minutes| *minutes < 60);
+
+		match (hours, minutes) {
+			(Some(hours), Some(minutes)) => Ok(hours * 60 + minutes),
+			_                            => bail!("\"{time}\" is not a valid time"),
+		}
+	};
+
+	let (start, end) = value.split_once('-').ok_or(anyhow!("must be formatted as HH:MM-HH:MM"))?;
+
+	Ok(Window { start: parse(start)?, end: parse(end)? })
+}
+
+#[test]
+fn test_window () {
+	let window = window_parser("02:00-04:30").unwrap();
+	assert_eq!(window, Window { start: 120, end: 270 });
+	assert!(  window.contains(120));
+	assert!(  window.contains(269));
+	assert!(! window.contains(270));
+	assert!(! window.contains(60));
+
+	let window = window_parser("22:30-01:00").unwrap();
+	assert!(  window.contains(23 * 60));
+	assert!(  window.contains(30));
+	assert!(! window.contains(12 * 60));
+
+	assert!(window_parser("24:00-01:00").is_err());
+	assert!(window_parser("02:00").is_err());
+	assert!(window_parser("2h-4h").is_err());
+}
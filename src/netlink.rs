@@ -0,0 +1,297 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use anyhow::Result;
+
+/// Netlink socket, see netlink(7) for details.
+pub(crate) struct Socket {
+	/// Underlying file descriptor.
+	fd: OwnedFd,
+
+	/// Sequence number of the last request.
+	sequence: u32,
+}
+
+impl Socket {
+	/// Opens a netlink socket for the specified `protocol`, ex. `libc::NETLINK_GENERIC`.
+	pub fn open (protocol: libc::c_int) -> Result<Self> {
+		let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, protocol) };
+		if fd < 0 {
+			bail!("can't create netlink socket: {}", std::io::Error::last_os_error());
+		}
+
+		Ok(Socket { fd: unsafe { OwnedFd::from_raw_fd(fd) }, sequence: 0 })
+	}
+
+	/// Sends a request made of `message_type` and `payload`, then returns the payloads of all replies.
+	///
+	/// The kernel is asked to acknowledge the request, so that the end
+	/// of the replies is always known.
+	pub fn request (&mut self, message_type: u16, flags: u16, payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+		self.sequence = self.sequence.wrapping_add(1);
+
+		let flags  = flags | libc::NLM_F_REQUEST as u16 | libc::NLM_F_ACK as u16;
+		let length = NLMSG_HDRLEN + payload.len();
+
+		let mut message = Vec::with_capacity(length);
+		message.extend_from_slice(&(length as u32).to_ne_bytes());
+		message.extend_from_slice(&message_type.to_ne_bytes());
+		message.extend_from_slice(&flags.to_ne_bytes());
+		message.extend_from_slice(&self.sequence.to_ne_bytes());
+		message.extend_from_slice(&0u32.to_ne_bytes());
+		message.extend_from_slice(payload);
+
+		let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+		address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+
+		let status = unsafe {
+			libc::sendto(self.fd.as_raw_fd(), message.as_ptr() as *const libc::c_void, message.len(), 0,
+			             std::ptr::addr_of!(address) as *const libc::sockaddr,
+			             std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t)
+		};
+
+		if status < 0 {
+			bail!("can't send netlink request: {}", std::io::Error::last_os_error());
+		}
+
+		let mut replies = Vec::new();
+
+		loop {
+			let buffer = self.receive()?;
+			let mut remaining = buffer.as_slice();
+
+			while remaining.len() >= NLMSG_HDRLEN {
+				let length       = u32::from_ne_bytes(remaining[0..4].try_into().unwrap()) as usize;
+				let message_type = u16::from_ne_bytes(remaining[4..6].try_into().unwrap());
+				let sequence     = u32::from_ne_bytes(remaining[8..12].try_into().unwrap());
+
+				if length < NLMSG_HDRLEN || length > remaining.len() {
+					bail!("malformed netlink message");
+				}
+
+				let payload = &remaining[NLMSG_HDRLEN..length];
+				remaining   = &remaining[align(length).min(remaining.len())..];
+
+				if sequence != self.sequence {
+					log::debug!("ignoring netlink message with sequence number {sequence}");
+					continue;
+				}
+
+				match message_type as libc::c_int {
+					libc::NLMSG_DONE  => return Ok(replies),
+					libc::NLMSG_ERROR => {
+						let error = i32::from_ne_bytes(payload.get(0..4).ok_or(anyhow!("malformed netlink error"))?.try_into().unwrap());
+						if error == 0 {
+							return Ok(replies);
+						}
+						bail!("netlink request failed: {}", std::io::Error::from_raw_os_error(-error));
+					}
+					_ => replies.push(payload.to_vec()),
+				}
+			}
+		}
+	}
+
+	/// Receives a whole datagram, whatever its size.
+	fn receive (&self) -> Result<Vec<u8>> {
+		let fd = self.fd.as_raw_fd();
+
+		let length = unsafe { libc::recv(fd, std::ptr::null_mut(), 0, libc::MSG_PEEK | libc::MSG_TRUNC) };
+		if length < 0 {
+			bail!("can't receive netlink reply: {}", std::io::Error::last_os_error());
+		}
+
+		let mut buffer = vec![0u8; length as usize];
+
+		let length = unsafe { libc::recv(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+		if length < 0 {
+			bail!("can't receive netlink reply: {}", std::io::Error::last_os_error());
+		}
+
+		buffer.truncate(length as usize);
+
+		Ok(buffer)
+	}
+}
+
+/// Resolves the identifier of the generic netlink family named `name`, ex. "ethtool".
+pub(crate) fn resolve_family (socket: &mut Socket, name: &str) -> Result<u16> {
+	let mut attributes = Attributes::new();
+	attributes.push_string(CTRL_ATTR_FAMILY_NAME, name);
+
+	let replies = socket.request(GENL_ID_CTRL, 0, &attributes.into_genl(CTRL_CMD_GETFAMILY, 1))
+	              .map_err(|error| anyhow!("can't resolve generic netlink family \"{name}\": {error}"))?;
+
+	for reply in &replies {
+		for (kind, value) in parse(reply.get(GENL_HDRLEN..).unwrap_or_default()) {
+			if kind == CTRL_ATTR_FAMILY_ID && value.len() >= 2 {
+				return Ok(u16::from_ne_bytes([value[0], value[1]]));
+			}
+		}
+	}
+
+	bail!("can't find generic netlink family \"{name}\"")
+}
+
+/// Builder of netlink attributes.
+#[derive(Default)]
+pub(crate) struct Attributes(Vec<u8>);
+
+impl Attributes {
+	/// Creates an empty list of attributes.
+	pub fn new () -> Self {
+		Default::default()
+	}
+
+	/// Appends an attribute holding raw `data`.
+	pub fn push (&mut self, kind: u16, data: &[u8]) -> &mut Self {
+		let length = NLA_HDRLEN + data.len();
+
+		self.0.extend_from_slice(&(length as u16).to_ne_bytes());
+		self.0.extend_from_slice(&kind.to_ne_bytes());
+		self.0.extend_from_slice(data);
+		self.0.resize(align(self.0.len()), 0);
+
+		self
+	}
+
+	/// Appends an attribute holding a `u32`.
+	pub fn push_u32 (&mut self, kind: u16, value: u32) -> &mut Self {
+		self.push(kind, &value.to_ne_bytes())
+	}
+
+	/// Appends an attribute holding a nul-terminated string.
+	pub fn push_string (&mut self, kind: u16, value: &str) -> &mut Self {
+		let mut data = value.as_bytes().to_vec();
+		data.push(0);
+		self.push(kind, &data)
+	}
+
+	/// Appends an attribute holding the nested `attributes`.
+	pub fn push_nested (&mut self, kind: u16, attributes: Attributes) -> &mut Self {
+		self.push(kind | NLA_F_NESTED, &attributes.0)
+	}
+
+	/// Gets the payload of a generic netlink message made of `command` and these attributes.
+	pub fn into_genl (self, command: u8, version: u8) -> Vec<u8> {
+		let mut payload = vec![command, version, 0, 0];
+		payload.extend_from_slice(&self.0);
+		payload
+	}
+}
+
+/// Parses the netlink attributes in `data`, ie. yields their type and their content.
+pub(crate) fn parse (mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+	std::iter::from_fn(move || {
+		if data.len() < NLA_HDRLEN {
+			return None;
+		}
+
+		let length = u16::from_ne_bytes([data[0], data[1]]) as usize;
+		let kind   = u16::from_ne_bytes([data[2], data[3]]) & NLA_TYPE_MASK;
+
+		if length < NLA_HDRLEN || length > data.len() {
+			log::warn!("malformed netlink attribute");
+			return None;
+		}
+
+		let value = &data[NLA_HDRLEN..length];
+		data      = &data[align(length).min(data.len())..];
+
+		Some((kind, value))
+	})
+}
+
+/// Reads a `u32` attribute content.
+pub(crate) fn read_u32 (value: &[u8]) -> Option<u32> {
+	Some(u32::from_ne_bytes(value.get(0..4)?.try_into().ok()?))
+}
+
+/// Reads a `u64` attribute content.
+pub(crate) fn read_u64 (value: &[u8]) -> Option<u64> {
+	Some(u64::from_ne_bytes(value.get(0..8)?.try_into().ok()?))
+}
+
+/// Reads a nul-terminated string attribute content.
+pub(crate) fn read_string (value: &[u8]) -> String {
+	String::from_utf8_lossy(value).trim_end_matches('\0').into()
+}
+
+/// Rounds `length` up to the netlink alignment.
+fn align (length: usize) -> usize {
+	(length + 3) & !3
+}
+
+#[test]
+fn test_attributes () {
+	let mut nested = Attributes::new();
+	nested.push_u32(1, 0x12345678);
+
+	let mut attributes = Attributes::new();
+	attributes.push_string(2, "eth1").push_nested(3, nested);
+
+	let data       = attributes.0;
+	let attributes = parse(&data).collect::<Vec<_>>();
+
+	assert_eq!(data.len(), 4 + 8 + 4 + 8);
+	assert_eq!(attributes.len(), 2);
+	assert_eq!(attributes[0].0, 2);
+	assert_eq!(read_string(attributes[0].1), "eth1");
+	assert_eq!(attributes[1].0, 3);
+	assert_eq!(parse(attributes[1].1).map(|(kind, value)| (kind, read_u32(value))).collect::<Vec<_>>(), vec![(1, Some(0x12345678))]);
+}
+
+/// Length of a netlink message header.
+const NLMSG_HDRLEN: usize = 16;
+
+/// Length of a netlink attribute header.
+const NLA_HDRLEN: usize = 4;
+
+/// Length of a generic netlink message header.
+pub(crate) const GENL_HDRLEN: usize = 4;
+
+/// Flag of nested netlink attributes.
+const NLA_F_NESTED: u16 = 1 << 15;
+
+/// Mask of the type of netlink attributes.
+const NLA_TYPE_MASK: u16 = !(1 << 15 | 1 << 14);
+
+/// Generic netlink controller.
+const GENL_ID_CTRL: u16 = 0x10;
+
+/// Command to get a generic netlink family.
+const CTRL_CMD_GETFAMILY: u8 = 3;
+
+/// Attribute holding the identifier of a generic netlink family.
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+
+/// Attribute holding the name of a generic netlink family.
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
@@ -0,0 +1,181 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::HashMap;
+
+use crate::netlink::{self, Socket};
+use anyhow::Result;
+
+/// Gets the generic statistics of the specified `device`, as reported by `IFLA_STATS64`.
+///
+/// Statistics are named after the fields of `struct rtnl_link_stats64`, ex. "rx_crc_errors".
+pub(crate) fn get_stats64 (device: &str) -> Result<HashMap<String, u64>> {
+	for (kind, value) in get_link(device)?.attributes {
+		if kind == IFLA_STATS64 {
+			return Ok(std::iter::zip(STATS64_NAMES, value.chunks_exact(8))
+			          .map(|(name, value)| (String::from(name), u64::from_ne_bytes(value.try_into().unwrap())))
+			          .collect());
+		}
+	}
+
+	bail!("can't find IFLA_STATS64 attribute for device {device}")
+}
+
+/// Gets the link state of the specified `device`.
+pub(crate) fn get_link_state (device: &str) -> Result<LinkState> {
+	let link = get_link(device)?;
+
+	let mut state = LinkState {
+		running:         link.flags & libc::IFF_RUNNING as u32 != 0,
+		operstate:       "unknown",
+		carrier_changes: None,
+	};
+
+	for (kind, value) in link.attributes {
+		match kind {
+			IFLA_OPERSTATE       => state.operstate = value.first().and_then(|index| OPERSTATES.get(*index as usize)).unwrap_or(&"unknown"),
+			IFLA_CARRIER_CHANGES => state.carrier_changes = netlink::read_u32(&value),
+			_                    => {}
+		}
+	}
+
+	Ok(state)
+}
+
+/// Link state of a network device.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LinkState {
+	/// Whether the link is operational, ie. `IFF_RUNNING`.
+	pub running: bool,
+
+	/// Operational state as defined by RFC 2863, ex. "up" or "lowerlayerdown".
+	pub operstate: &'static str,
+
+	/// Number of times the carrier went up or down, if reported.
+	pub carrier_changes: Option<u32>,
+}
+
+impl std::fmt::Display for LinkState {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		write!(formatter, "{} ({})", self.operstate, if self.running { "running" } else { "not running" })?;
+
+		if let Some(carrier_changes) = self.carrier_changes {
+			write!(formatter, ", {carrier_changes} carrier changes")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Link information, as reported by `RTM_GETLINK`.
+struct Link {
+	/// Device flags, ex. `IFF_RUNNING`.
+	flags: u32,
+
+	/// All attributes, ie. their type and their content.
+	attributes: Vec<(u16, Vec<u8>)>,
+}
+
+/// Gets the link information of the specified `device`, through `RTM_GETLINK`.
+fn get_link (device: &str) -> Result<Link> {
+	let name  = std::ffi::CString::new(device)?;
+	let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+
+	if index == 0 {
+		bail!("can't find index of device {device}: {}", std::io::Error::last_os_error());
+	}
+
+	// struct ifinfomsg { u8 family; u8 pad; u16 type; i32 index; u32 flags; u32 change; }
+	let mut ifinfomsg = vec![0u8; IFINFOMSG_LEN];
+	ifinfomsg[4..8].copy_from_slice(&(index as i32).to_ne_bytes());
+
+	let mut socket  = Socket::open(libc::NETLINK_ROUTE)?;
+	let     replies = socket.request(libc::RTM_GETLINK, 0, &ifinfomsg)
+	                  .map_err(|error| anyhow!("can't get link information for device {device}: {error}"))?;
+
+	let reply = replies.first().ok_or(anyhow!("no link information for device {device}"))?;
+
+	Ok(Link {
+		flags:      netlink::read_u32(reply.get(8..12).unwrap_or_default()).unwrap_or(0),
+		attributes: netlink::parse(reply.get(IFINFOMSG_LEN..).unwrap_or_default())
+		            .map(|(kind, value)| (kind, value.to_vec()))
+		            .collect(),
+	})
+}
+
+/// Length of `struct ifinfomsg`.
+const IFINFOMSG_LEN: usize = 16;
+
+/// Attribute holding the operational state.
+const IFLA_OPERSTATE: u16 = 16;
+
+/// Attribute holding `struct rtnl_link_stats64`.
+const IFLA_STATS64: u16 = 23;
+
+/// Attribute holding the number of carrier changes.
+const IFLA_CARRIER_CHANGES: u16 = 35;
+
+/// Operational states, as defined by RFC 2863.
+const OPERSTATES: [&str; 7] = [
+	"unknown",
+	"notpresent",
+	"down",
+	"lowerlayerdown",
+	"testing",
+	"dormant",
+	"up",
+];
+
+/// Names of the fields of `struct rtnl_link_stats64`, in order.
+const STATS64_NAMES: [&str; 23] = [
+	"rx_packets",
+	"tx_packets",
+	"rx_bytes",
+	"tx_bytes",
+	"rx_errors",
+	"tx_errors",
+	"rx_dropped",
+	"tx_dropped",
+	"multicast",
+	"collisions",
+	"rx_length_errors",
+	"rx_over_errors",
+	"rx_crc_errors",
+	"rx_frame_errors",
+	"rx_fifo_errors",
+	"rx_missed_errors",
+	"tx_aborted_errors",
+	"tx_carrier_errors",
+	"tx_fifo_errors",
+	"tx_heartbeat_errors",
+	"tx_window_errors",
+	"rx_compressed",
+	"tx_compressed",
+];
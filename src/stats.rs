@@ -0,0 +1,202 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::device_tree;
+use crate::ethtool;
+use anyhow::{Context, Result};
+
+/// Prints all NIC statistics of the device selected by `selector`.
+///
+/// Only the statistics named in `selection` are printed, unless it is
+/// empty.  The snapshot is also saved as JSON into `save`, if specified.
+pub(crate) fn print (selector: &str, selection: &[String], save: Option<&Path>) -> Result<()> {
+	let device   = device_tree::find_device(selector)?;
+	let snapshot = Snapshot::take(&device)?;
+
+	let stats = snapshot.stats.iter()
+	            .filter(|(name, _)| is_selected(selection, name))
+	            .collect::<BTreeMap<_, _>>();
+
+	let width = stats.keys().map(|name| name.len()).max().unwrap_or(0);
+
+	for (name, value) in stats {
+		println!("{name:width$} {value}");
+	}
+
+	if let Some(path) = save {
+		snapshot.save(path)?;
+	}
+
+	Ok(())
+}
+
+/// Prints the deltas of NIC statistics of the device selected by `selector` every `interval`.
+///
+/// Only the statistics named in `selection` are printed, even if
+/// they didn't change, unless it is empty, in which case all
+/// non-zero deltas are printed.
+pub(crate) fn watch (selector: &str, selection: &[String], interval: Duration) -> Result<()> {
+	let device = device_tree::find_device(selector)?;
+	let start  = Instant::now();
+
+	let mut before = ethtool::get_nic_stats(&device)?;
+
+	loop {
+		std::thread::sleep(interval);
+
+		let after = ethtool::get_nic_stats(&device)?;
+
+		println!("+{:.2}s", start.elapsed().as_secs_f32());
+
+		if selection.is_empty() {
+			for (name, delta) in ethtool::diff(&before, &after) {
+				println!("\t{name}: +{delta}");
+			}
+		} else {
+			for name in selection {
+				match (before.get(name), after.get(name)) {
+					(Some(before), Some(after)) => println!("\t{name}: +{}", after.saturating_sub(*before)),
+					_                           => println!("\t{name}: unknown"),
+				}
+			}
+		}
+
+		before = after;
+	}
+}
+
+/// Prints the non-zero deltas between the snapshots saved into `before` and `after`.
+///
+/// Only the statistics named in `selection` are printed, unless it is empty.
+pub(crate) fn diff (before: &Path, after: &Path, selection: &[String]) -> Result<()> {
+	let before = Snapshot::load(before)?;
+	let after  = Snapshot::load(after)?;
+
+	if before.device != after.device {
+		log::warn!("comparing snapshots of different devices: \"{}\" and \"{}\"", before.device, after.device);
+	}
+
+	println!("Snapshots of device \"{}\" taken {:.2}s apart", after.device, after.timestamp - before.timestamp);
+
+	for (name, delta) in ethtool::diff(&before.stats, &after.stats) {
+		if is_selected(selection, &name) {
+			println!("\t{name}: +{delta}");
+		}
+	}
+
+	Ok(())
+}
+
+/// Checks whether the statistic `name` is in `selection`, an empty selection means all.
+fn is_selected (selection: &[String], name: &str) -> bool {
+	selection.is_empty() || selection.iter().any(|selected| selected == name)
+}
+
+/// Snapshot of all NIC statistics of a device.
+struct Snapshot {
+	/// Device name.
+	device: String,
+
+	/// Seconds since Unix epoch when this snapshot was taken.
+	timestamp: f64,
+
+	/// All NIC statistics.
+	stats: HashMap<String, u64>,
+}
+
+impl Snapshot {
+	/// Takes a snapshot of all NIC statistics of `device`.
+	fn take (device: &str) -> Result<Self> {
+		let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+
+		Ok(Snapshot {
+			device:    String::from(device),
+			timestamp,
+			stats:     ethtool::get_nic_stats(device)?,
+		})
+	}
+
+	/// Saves this snapshot as JSON into `path`.
+	fn save (&self, path: &Path) -> Result<()> {
+		let json = serde_json::json!({
+			"device":    self.device,
+			"timestamp": self.timestamp,
+			"stats":     self.stats.iter().collect::<BTreeMap<_, _>>(),
+		});
+
+		std::fs::write(path, format!("{json:#}\n")).with_context(|| format!("can't write {}", path.display()))
+	}
+
+	/// Loads a snapshot saved as JSON into `path`.
+	fn load (path: &Path) -> Result<Self> {
+		let content = std::fs::read_to_string(path).map_err(|error| anyhow!("can't read {}: {error}", path.display()))?;
+		let json    = serde_json::from_str::<serde_json::Value>(&content).with_context(|| format!("can't parse {}", path.display()))?;
+		let error   = || anyhow!("{} is not a valid snapshot", path.display());
+
+		let stats = json["stats"].as_object().ok_or_else(error)?
+		            .iter()
+		            .map(|(name, value)| Ok((name.clone(), value.as_u64().ok_or_else(error)?)))
+		            .collect::<Result<_>>()?;
+
+		Ok(Snapshot {
+			device:    String::from(json["device"].as_str().ok_or_else(error)?),
+			timestamp: json["timestamp"].as_f64().ok_or_else(error)?,
+			stats,
+		})
+	}
+}
+
+#[test]
+fn test_snapshot () {
+	let path     = std::env::temp_dir().join(format!("stm32mp25-rgmii-gtx-clock-delay-snapshot-{}.json", std::process::id()));
+	let snapshot = Snapshot {
+		device:    String::from("eth1"),
+		timestamp: 1700000000.5,
+		stats:     HashMap::from([(String::from("mmc_rx_crc_error"), 3), (String::from("rx_pkt_n"), 1000)]),
+	};
+
+	snapshot.save(&path).unwrap();
+	let loaded = Snapshot::load(&path).unwrap();
+
+	assert_eq!((loaded.device, loaded.timestamp, loaded.stats), (snapshot.device, snapshot.timestamp, snapshot.stats));
+
+	std::fs::write(&path, r#"{"device": "eth1", "timestamp": 0, "stats": {"rx_pkt_n": -1}}"#).unwrap();
+	assert!(Snapshot::load(&path).is_err());
+
+	std::fs::remove_file(path).unwrap();
+
+	assert!(is_selected(&[], "rx_pkt_n"));
+	assert!(is_selected(&[String::from("rx_pkt_n")], "rx_pkt_n"));
+	assert!(! is_selected(&[String::from("rx_pkt_n")], "mmc_rx_crc_error"));
+}
@@ -0,0 +1,153 @@
+// Copyright 2023 STMicroelectronics
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the
+//    distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its
+//    contributors may be used to endorse or promote products derived
+//    from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+use crate::benchmark::counters::Counters;
+use crate::clock_delay;
+use crate::rtnetlink;
+use anyhow::Result;
+
+/// Continuously displays the clock delay, the CRC error rate, the packet rate and the link state.
+///
+/// The clock delay is highlighted whenever it changes underneath, and
+/// the CRC error rate whenever it exceeds `threshold` (in percent).
+pub(crate) fn perform (selector: &str, interval: Duration, threshold: f32, counters: Option<Counters>) -> Result<()> {
+	let handle = clock_delay::Handle::resolve(selector)?;
+	let device = handle.device.as_str();
+
+	let counters = match counters {
+		Some(counters) => counters,
+		None           => Counters::detect(device)?,
+	};
+
+	let terminal = std::io::stdout().is_terminal();
+	let start    = Instant::now();
+
+	let mut previous_bits    = handle.value.get()?;
+	let mut previous_counts  = counters.read(device)?;
+	let mut previous_instant = Instant::now();
+	let mut last_change      = None;
+
+	loop {
+		std::thread::sleep(interval);
+
+		let bits    = handle.value.get()?;
+		let ns      = handle.value.get_as_ns()?;
+		let counts  = counters.read(device)?;
+		let instant = Instant::now();
+
+		let link = match rtnetlink::get_link_state(device) {
+			Ok(link)   => link.to_string(),
+			Err(error) => format!("unknown ({error})"),
+		};
+
+		let crc_errors = counts.0.saturating_sub(previous_counts.0);
+		let packets    = counts.1.saturating_sub(previous_counts.1);
+		let elapsed    = (instant - previous_instant).as_secs_f32();
+
+		let changed = bits != previous_bits;
+		if changed {
+			last_change = Some((start.elapsed(), previous_bits));
+		}
+
+		let delay = format!("{bits:#x} ({ns} nanoseconds)");
+		let delay = if changed {
+			highlight(terminal, &format!("{delay}, changed from {previous_bits:#x}"))
+		} else {
+			delay
+		};
+
+		let rate = format_rate(terminal, crc_errors, packets, threshold);
+
+		if terminal {
+			print!("\x1b[2J\x1b[H");
+			println!("Watching device \"{device}\" every {:.2}s (+{:.0}s)", interval.as_secs_f32(), start.elapsed().as_secs_f32());
+			println!("  RGMII GTX clock delay: {delay}");
+			println!("  CRC error rate:        {rate}");
+			println!("  Packet rate:           {:.0} packets/s", packets as f32 / elapsed);
+			println!("  Link state:            {link}");
+
+			if let Some((when, from)) = last_change {
+				println!("  Last delay change:     at +{:.0}s, from {from:#x}", when.as_secs_f32());
+			}
+		} else {
+			println!("{}", format_line(start.elapsed(), &delay, &rate, packets as f32 / elapsed, &link));
+		}
+
+		previous_bits    = bits;
+		previous_counts  = counts;
+		previous_instant = instant;
+	}
+}
+
+/// Formats the CRC error rate of `crc_errors` over `packets`, highlighted if above `threshold` (in percent).
+///
+/// The rate is "-" if no packet was received.
+fn format_rate (terminal: bool, crc_errors: u64, packets: u64, threshold: f32) -> String {
+	if packets == 0 {
+		return String::from("-");
+	}
+
+	let percent = (100 * crc_errors) as f32 / packets as f32;
+	let rate    = format!("{percent:.2}% ({crc_errors}/{packets})");
+
+	if percent > threshold {
+		highlight(terminal, &format!("{rate}, above {threshold:.2}%"))
+	} else {
+		rate
+	}
+}
+
+/// Formats a single line of output when it isn't a terminal, `elapsed` since the start.
+fn format_line (elapsed: Duration, delay: &str, rate: &str, packet_rate: f32, link: &str) -> String {
+	format!("+{:.0}s: delay {delay}; CRC error rate {rate}; {packet_rate:.0} packets/s; link {link}", elapsed.as_secs_f32())
+}
+
+/// Highlights `text` if the output is a `terminal`, or marks it with exclamation marks otherwise.
+fn highlight (terminal: bool, text: &str) -> String {
+	if terminal {
+		format!("\x1b[1;31m{text}\x1b[0m")
+	} else {
+		format!("{text} (!)")
+	}
+}
+
+#[test]
+fn test_format () {
+	assert_eq!(format_rate(false, 0, 0, 0.1),    "-");
+	assert_eq!(format_rate(false, 1, 1000, 0.1), "0.10% (1/1000)");
+	assert_eq!(format_rate(false, 2, 1000, 0.1), "0.20% (2/1000), above 0.10% (!)");
+	assert_eq!(format_rate(true, 2, 1000, 0.1),  "\x1b[1;31m0.20% (2/1000), above 0.10%\x1b[0m");
+
+	assert_eq!(format_line(Duration::from_secs(3), "0x3 (0.75 nanoseconds)", "-", 0.0, "up"),
+	           "+3s: delay 0x3 (0.75 nanoseconds); CRC error rate -; 0 packets/s; link up");
+}